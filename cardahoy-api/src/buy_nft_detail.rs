@@ -29,7 +29,7 @@ pub struct BuyNftDetailResponse {
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UserBalance {
-    pub balance: Option<u32>,
+    pub balance: Option<f64>,
     // 货币单位
     pub price_unity: String,
 }
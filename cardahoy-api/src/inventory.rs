@@ -0,0 +1,199 @@
+use super::{market_home::AccumulateTrait, nft};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserNftListResponse {
+    pub total: u32,
+    pub list: Vec<UserNft>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserNft {
+    // nft::NftId::Cards
+    pub chain_nft_id: u32,
+    // nft::NftCardId 具体的数字值
+    pub second_category_id: u32,
+    // 卡牌唯一id
+    pub token_id: String,
+    // 卡牌名称 #token_id
+    pub nft_name: String,
+    pub image: String,
+    // 数量
+    pub amount: u32,
+    // 卡牌等级
+    pub priority_trait1: String,
+    // 卡牌经验信息
+    pub accumulate_trait: AccumulateTrait,
+    // 是否在售
+    pub if_on_sale: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSaleListResponse {
+    pub total: u32,
+    pub list: Vec<UserSale>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSale {
+    // 交易编码, 用于下架
+    pub sale_aggregator_number: String,
+    pub chain_nft_id: u32,
+    pub second_category_id: u32,
+    pub token_id: String,
+    pub nft_name: String,
+    pub image: String,
+    pub amount: u32,
+    // 售价
+    pub sale_price: String,
+    // 货币单位
+    pub price_unity: String,
+    // 卡牌等级
+    pub priority_trait1: String,
+    // 卡牌经验信息
+    pub accumulate_trait: AccumulateTrait,
+    // 挂单时间
+    pub ground_time: u64,
+    // 挂单过期时间
+    pub end_time: u64,
+}
+
+impl super::CardsAhoyApi {
+    /// Queries the cards owned by the logged-in account.
+    ///
+    /// This function sends a POST request to fetch the account's NFT assets of the given kind, one page at a time. Each entry carries the token id, level and EXP needed to price or list the card.
+    ///
+    /// # Arguments
+    ///
+    /// * `nft_id` - The kind of NFT to list, usually `nft::NftId::Cards`.
+    /// * `page` - The page number for pagination.
+    /// * `page_size` - The number of entries per page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` type containing `UserNftListResponse` on success, or an error if the request fails.
+    pub async fn query_user_nft_list(
+        &self,
+        nft_id: nft::NftId,
+        page: u32,
+        page_size: u32,
+    ) -> Result<UserNftListResponse> {
+        let payload = json!({
+            "chainNftId": nft_id as u32,
+            "pageNumber": page,
+            "pageSize": page_size,
+        });
+
+        let result = self
+            .post::<UserNftListResponse>(
+                "https://game.metalist.io/api/marketQuery/queryUserNftList",
+                payload,
+            )
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Queries the listings the logged-in account currently has on sale.
+    ///
+    /// This function sends a POST request to fetch the account's active listings of the given kind, one page at a time. The returned `sale_aggregator_number` is what `cancel_ground_nft_asset` expects.
+    ///
+    /// # Arguments
+    ///
+    /// * `nft_id` - The kind of NFT to list, usually `nft::NftId::Cards`.
+    /// * `coin_id` - Only listings priced in this trade coin are returned.
+    /// * `page` - The page number for pagination.
+    /// * `page_size` - The number of entries per page.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` type containing `UserSaleListResponse` on success, or an error if the request fails.
+    pub async fn query_user_sale_list(
+        &self,
+        nft_id: nft::NftId,
+        coin_id: u32,
+        page: u32,
+        page_size: u32,
+    ) -> Result<UserSaleListResponse> {
+        let payload = json!({
+            "chainNftId": nft_id as u32,
+            "coinId": coin_id,
+            "pageNumber": page,
+            "pageSize": page_size,
+        });
+
+        let result = self
+            .post::<UserSaleListResponse>(
+                "https://game.metalist.io/api/marketQuery/queryUserSaleList",
+                payload,
+            )
+            .await?;
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_nft_list() {
+        let resp: UserNftListResponse = serde_json::from_value(json!({
+            "total": 1,
+            "list": [{
+                "chainNftId": 12,
+                "secondCategoryId": 320,
+                "tokenId": "1024",
+                "nftName": "Ammo Crate #1024",
+                "image": "",
+                "amount": 1,
+                "priorityTrait1": "2",
+                "accumulateTrait": { "name": "EXP", "value": 3 },
+                "ifOnSale": false
+            }]
+        }))
+        .unwrap();
+        assert_eq!(resp.total, 1);
+        let nft = &resp.list[0];
+        assert_eq!(nft.second_category_id, 320);
+        assert_eq!(nft.token_id, "1024");
+        assert_eq!(nft.accumulate_trait.value, 3);
+        assert!(!nft.if_on_sale);
+    }
+
+    #[test]
+    fn test_user_sale_list() {
+        let resp: UserSaleListResponse = serde_json::from_value(json!({
+            "total": 1,
+            "list": [{
+                "saleAggregatorNumber": "SA20240425000001",
+                "chainNftId": 12,
+                "secondCategoryId": 320,
+                "tokenId": "1024",
+                "nftName": "Ammo Crate #1024",
+                "image": "",
+                "amount": 1,
+                "salePrice": "0.5",
+                "priceUnity": "MATIC",
+                "priorityTrait1": "2",
+                "accumulateTrait": { "name": "EXP", "value": 3 },
+                "groundTime": 1714000000000u64,
+                "endTime": 1714086400000u64
+            }]
+        }))
+        .unwrap();
+        let sale = &resp.list[0];
+        assert_eq!(sale.sale_aggregator_number, "SA20240425000001");
+        assert_eq!(sale.token_id, "1024");
+        assert_eq!(sale.sale_price, "0.5");
+        assert_eq!(sale.price_unity, "MATIC");
+        assert_eq!(sale.end_time - sale.ground_time, 24 * 60 * 60 * 1000);
+    }
+}
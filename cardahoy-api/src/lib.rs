@@ -1,6 +1,6 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use cardahoy_crypto as crypto;
 use cardahoy_utils as utils;
 use reqwest::{
@@ -8,17 +8,20 @@ use reqwest::{
     StatusCode,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::Value;
+use zeroize::Zeroize;
 pub mod analyze;
 pub mod buy_nft_detail;
 pub mod category_list;
 pub mod filter;
 pub mod game_config;
+pub mod inventory;
 pub mod market_home;
 pub mod market_secondary;
 pub mod nft;
 pub mod payment;
 pub mod sell;
+pub mod telemetry;
 pub mod user_balance;
 
 #[derive(Deserialize, Debug)]
@@ -47,14 +50,115 @@ pub struct PwdLoginParams {
     pub user_number: String,
 }
 
+// 密码在请求加密后清零
+impl Drop for BuyNftParams {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+impl Drop for PwdLoginParams {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+// 未登录或登录已过期
+pub const CODE_NOT_LOGGED: &str = "100000";
+
+/// An error response from the game API, i.e. any `code` other than "000000".
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub code: String,
+    pub msg: String,
+}
+
+impl ApiError {
+    pub fn is_session_expired(&self) -> bool {
+        self.code == CODE_NOT_LOGGED
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Whether `err` was caused by the API rejecting an expired login.
+pub fn is_session_expired(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<ApiError>()
+            .is_some_and(ApiError::is_session_expired)
+    })
+}
+
 const POST_MAX_RETRY: u32 = 4;
+
+// 接口名，即 URL 的最后一段，例如 queryMarketHome
+fn endpoint(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+// 记录一次请求的结果与耗时
+fn record_request(endpoint: &str, outcome: &'static str, started: Instant) {
+    let endpoint = endpoint.to_string();
+    metrics::counter!(telemetry::API_REQUESTS, "endpoint" => endpoint.clone(), "outcome" => outcome)
+        .increment(1);
+    metrics::histogram!(telemetry::API_REQUEST_DURATION, "endpoint" => endpoint)
+        .record(started.elapsed().as_secs_f64());
+}
+
+// 内置的平台 RSA 公钥，未配置 rsa_public_key 或 rsa_key_path 时使用
+pub const DEFAULT_PUBLIC_KEY: &str = include_str!("../../key.pem");
+
+/// Loads the RSA public key from `rsa_public_key`, `rsa_key_path` or the
+/// embedded default, in that order.
+pub fn load_public_key(config: &utils::Config) -> Result<crypto::PublicKey> {
+    match (&config.rsa_public_key, &config.rsa_key_path) {
+        (Some(pem), _) => crypto::PublicKey::from_pem(pem).context("invalid rsa_public_key"),
+        (None, Some(path)) => {
+            let pem = std::fs::read_to_string(path)
+                .with_context(|| format!("unable to read RSA key {}", path))?;
+            crypto::PublicKey::from_pem(&pem).with_context(|| format!("invalid RSA key {}", path))
+        }
+        (None, None) => Ok(crypto::PublicKey::from_pem(DEFAULT_PUBLIC_KEY)?),
+    }
+}
+
 pub struct CardsAhoyApi {
     client: reqwest::Client,
+    // 启动时解析一次，加密每个请求的 AES 密钥
+    public_key: crypto::PublicKey,
+    // 两次请求之间的最小间隔，每个账户的客户端单独限速
+    request_interval: Duration,
+    last_request: tokio::sync::Mutex<Option<Instant>>,
+    // 加密请求的 reqTimestamp、nonce 与会话密钥来源，测试时可固定
+    clock: Box<dyn utils::Clock>,
+    rng: Box<dyn crypto::RandomSource>,
 }
 
 impl CardsAhoyApi {
-    pub fn new() -> Result<CardsAhoyApi> {
-        let config = utils::Config::new();
+    pub fn new(config: &utils::Config) -> Result<CardsAhoyApi> {
+        Self::with_providers(
+            config,
+            Box::new(utils::SystemClock),
+            Box::new(crypto::OsRandom),
+        )
+    }
+
+    /// Builds a client whose encrypted requests take their timestamps from
+    /// `clock` and their nonces, session keys and RSA padding from `rng`.
+    /// With the `openssl` feature the padding, and so `encKey`, stays random.
+    pub fn with_providers(
+        config: &utils::Config,
+        clock: Box<dyn utils::Clock>,
+        rng: Box<dyn crypto::RandomSource>,
+    ) -> Result<CardsAhoyApi> {
+        let public_key = load_public_key(config)?;
         let client = reqwest::Client::builder()
             .user_agent(&config.user_agent)
             .default_headers({
@@ -76,7 +180,26 @@ impl CardsAhoyApi {
             })
             .build()?;
 
-        Ok(CardsAhoyApi { client })
+        Ok(CardsAhoyApi {
+            client,
+            public_key,
+            request_interval: Duration::from_millis(config.request_interval_ms),
+            last_request: tokio::sync::Mutex::new(None),
+            clock,
+            rng,
+        })
+    }
+
+    // 等待到距离上次请求至少 request_interval 后再发送
+    async fn throttle(&self) {
+        if self.request_interval.is_zero() {
+            return;
+        }
+        let mut last = self.last_request.lock().await;
+        if let Some(last) = *last {
+            tokio::time::sleep_until((last + self.request_interval).into()).await;
+        }
+        *last = Some(Instant::now());
     }
 
     /// Sends a POST request with the given payload to the specified URL and
@@ -123,12 +246,26 @@ impl CardsAhoyApi {
         url: &str,
         payload: Value,
     ) -> Result<T> {
+        let endpoint = endpoint(url);
         for retry_count in 0..=POST_MAX_RETRY {
+            if retry_count > 0 {
+                metrics::counter!(telemetry::API_RETRIES, "endpoint" => endpoint.to_string())
+                    .increment(1);
+            }
+            self.throttle().await;
             let request_builder = self.client.post(url);
 
+            let started = Instant::now();
             let response = match &payload {
-                Value::Object(map) if map.is_empty() => request_builder.send().await?,
-                _ => request_builder.json(&payload).send().await?,
+                Value::Object(map) if map.is_empty() => request_builder.send().await,
+                _ => request_builder.json(&payload).send().await,
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    record_request(endpoint, "transport_error", started);
+                    return Err(e.into());
+                }
             };
 
             let status = response.status();
@@ -136,6 +273,7 @@ impl CardsAhoyApi {
             let response_body = response.text().await?;
             if status != StatusCode::OK && retry_count <= POST_MAX_RETRY {
                 // 状态码不为200，且重试次数不足，需要重试
+                record_request(endpoint, "http_error", started);
                 tracing::debug!("Retrying after 1 second...");
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 continue;
@@ -147,19 +285,34 @@ impl CardsAhoyApi {
                 && response_body.starts_with("<html>")
             {
                 // 状态码为200，返回的503页面，且重试次数不足，需要重试。
+                record_request(endpoint, "html_503", started);
+                metrics::counter!(telemetry::API_HTML_PAGES, "endpoint" => endpoint.to_string())
+                    .increment(1);
                 tracing::debug!("Retrying after 1 second...");
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 continue;
             }
 
-            let result: ResponseWrapper<T> = serde_json::from_str(&response_body)?;
+            let result: ResponseWrapper<T> = match serde_json::from_str(&response_body) {
+                Ok(result) => result,
+                Err(e) => {
+                    record_request(endpoint, "decode_error", started);
+                    return Err(e.into());
+                }
+            };
 
             // 000000为成功， 不成功，返回错误信息。这里一般为 Not Logged，需要重新登录。
             if result.code != "000000" {
+                record_request(endpoint, "api_error", started);
                 println!("network error");
                 println!("{:#?}", &result);
-                return Err(anyhow::anyhow!(result.msg));
+                return Err(ApiError {
+                    code: result.code,
+                    msg: result.msg,
+                }
+                .into());
             }
+            record_request(endpoint, "ok", started);
             // if result.code == "100000" {
             //     let token = self.pwd_login().await?;
             //     return Err(anyhow::anyhow!(result.msg));
@@ -184,6 +337,34 @@ impl CardsAhoyApi {
         unimplemented!("query_secondary_filter_list")
     }
 
+    // 加密请求参数，返回 {"encKey", "encContent"} 请求体
+    fn seal<T: Serialize>(&self, params: &T) -> Result<Value> {
+        let envelope =
+            crypto::EncryptedEnvelope::seal_with_rng(params, &self.public_key, self.rng.as_ref())?;
+        Ok(serde_json::to_value(envelope)?)
+    }
+
+    // 加密请求的 UUID v4 nonce
+    fn nonce(&self) -> String {
+        let mut bytes = [0u8; 16];
+        self.rng.fill_bytes(&mut bytes);
+        uuid::Builder::from_random_bytes(bytes)
+            .into_uuid()
+            .to_string()
+    }
+
+    fn buy_payload(&self, sale_aggregator_number: &str) -> Result<Value> {
+        let params = BuyNftParams {
+            nonce: self.nonce(),
+            amount: 1,
+            password: "".into(),
+            payment_type: "Wallet".into(),
+            req_timestamp: self.clock.timestamp(),
+            sale_aggregator_number: sale_aggregator_number.into(),
+        };
+        self.seal(&params)
+    }
+
     /// Initiates the purchase of an NFT asset using a sale aggregator number.
     ///
     /// This function constructs a payload to securely initiate the purchase of an NFT asset.
@@ -216,52 +397,8 @@ impl CardsAhoyApi {
     /// # }
     /// ```
     ///
-    fn encrypt<T: Serialize>(&self, data: T) -> Result<(String, String)> {
-        let data = serde_json::to_string(&data)?;
-        let key = crypto::random_key(16);
-        let pem = std::fs::read_to_string("key.pem")?;
-
-        let encrypted = crypto::rsa_pkcs1_encrypt(&key, &pem)?;
-        // let encrypted = crypto::rsa_encrypt(&key, pem)?;
-        let enc_key = crypto::base64_encode(&encrypted);
-        tracing::debug!("[RSA] enc_key: {:?} ", enc_key);
-
-        let encrypted_data = crypto::aes_ecb_encrypt(&data, &key)?;
-        let enc_content = crypto::base64_encode(&encrypted_data);
-        tracing::debug!("[AES] enc_content: {:?} ", enc_content);
-
-        Ok((enc_key, enc_content))
-    }
-
     pub async fn buy_ntf_asset(&self, sale_aggregator_number: &str) -> Result<String> {
-        let params = BuyNftParams {
-            nonce: uuid::Uuid::new_v4().to_string(),
-            amount: 1,
-            password: "".into(),
-            payment_type: "Wallet".into(),
-            req_timestamp: utils::timestamp(),
-            sale_aggregator_number: sale_aggregator_number.into(),
-        };
-
-        // let data = serde_json::to_string(&params)?;
-        // let key = crypto::random_key(16);
-        // let pem = std::fs::read_to_string("key.pem")?;
-
-        // let encrypted = crypto::rsa_pkcs1_encrypt(&key, &pem)?;
-        // // let encrypted = crypto::rsa_encrypt(&key, pem)?;
-        // let enc_key = crypto::base64_encode(&encrypted);
-        // tracing::debug!("[RSA] enc_key: {:?} ", enc_key);
-
-        // let encrypted_data = crypto::aes_ecb_encrypt(&data, &key)?;
-        // let enc_content = crypto::base64_encode(&encrypted_data);
-        // tracing::debug!("[AES] enc_content: {:?} ", enc_content);
-
-        let (enc_key, enc_content) = self.encrypt::<BuyNftParams>(params)?;
-
-        let payload = json!({
-            "encContent": enc_content,
-            "encKey": enc_key
-        });
+        let payload = self.buy_payload(sale_aggregator_number)?;
         tracing::debug!("[API] payload: {:?} ", payload);
 
         let result = self
@@ -282,25 +419,7 @@ impl CardsAhoyApi {
             user_number: "".into(),
         };
 
-        // let data = serde_json::to_string(&params)?;
-        // let key = crypto::random_key(16);
-        // let pem = std::fs::read_to_string("key.pem")?;
-
-        // let encrypted = crypto::rsa_pkcs1_encrypt(&key, &pem)?;
-        // // let encrypted = crypto::rsa_encrypt(&key, pem)?;
-        // let enc_key = crypto::base64_encode(&encrypted);
-        // tracing::debug!("[RSA] enc_key: {:?} ", enc_key);
-
-        // let encrypted_data = crypto::aes_ecb_encrypt(&data, &key)?;
-        // let enc_content = crypto::base64_encode(&encrypted_data);
-        // tracing::debug!("[AES] enc_content: {:?} ", enc_content);
-
-        let (enc_key, enc_content) = self.encrypt::<PwdLoginParams>(params)?;
-
-        let payload = json!({
-            "encContent": enc_content,
-            "encKey": enc_key
-        });
+        let payload = self.seal(&params)?;
         tracing::debug!("[API] payload: {:?} ", payload);
 
         let result = self
@@ -313,3 +432,116 @@ impl CardsAhoyApi {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_api(seed: u64) -> CardsAhoyApi {
+        let env = [(
+            "CARDAHOY_RSA_PUBLIC_KEY".to_string(),
+            include_str!("../../cardahoy-crypto/testdata/public.pem").to_string(),
+        )];
+        let config =
+            utils::Config::from_sources(include_str!("../../config.example.toml"), env).unwrap();
+        CardsAhoyApi::with_providers(
+            &config,
+            Box::new(utils::FixedClock(1714000000000)),
+            Box::new(crypto::SeededRandom::new(seed)),
+        )
+        .unwrap()
+    }
+
+    fn open(payload: &Value) -> Value {
+        let private_key = crypto::PrivateKey::from_pem(include_str!(
+            "../../cardahoy-crypto/testdata/private.pem"
+        ))
+        .unwrap();
+        let envelope: crypto::EncryptedEnvelope = serde_json::from_value(payload.clone()).unwrap();
+        envelope.open(&private_key).unwrap()
+    }
+
+    // 种子 1 生成的 nonce 与会话密钥 MbNLxQh9yYtHhJYi 下的 encContent，已用 openssl 解密核对；
+    // encKey 的填充同样取自种子（纯 Rust 实现），openssl 特性下填充随机，只核对 encContent
+    const ENC_KEY: &str = "KU3suVOTwwdar0TFG2TDAanKKE6JXrZG1ZCZtt9MDk1J5K1OpsE4aTXFnZXd/5hNfFUbkjJvfMNecip5FU3lDjpCrikQmPt2ZM6jq21/Rm+zI3miaaVq13wLWew1d7p3XYlH2svgtl6bsZ0OleClUGQwd50dGIRze9pg1GscFYs=";
+    const BUY_ENC_CONTENT: &str = "aZLgFN9/gdEMkV9I1vxhUbqjd6quoshAl9KKg5lYYZwGxYFaSBSF9y7kVBoUW9jfmCW1QKVhxGstOPaP6+J87Pf0MJYG0iHzGMp73xnel9S0HduTMrNCY4z92eRswOXYYJDZsyoQg35N5J0cpODIsB20M9PHDOpOEPbqkBFkbuvuNfdsuyFkyma87AVZFr9bomtQN+DJ1w+3LPrdIhMcUY0+Hyl0y/J2LUCCKos5ZMA=";
+    const SELL_ENC_CONTENT: &str = "aZLgFN9/gdEMkV9I1vxhUbqjd6quoshAl9KKg5lYYZwGxYFaSBSF9y7kVBoUW9jfmCW1QKVhxGstOPaP6+J87Edfk4ZPBtCQZTs/QfwfLsUPzgsVoshbPcGzvbBL6Bp/VqLvO14AzhRBeX0wL2t09BXJB2NcUEleebEBWjUI6l3GLx/F91e2CdmQFCHYtFqwypbtZG8SUwu1GYaNmjbduguppCYcEQotPRAVPFE1G6o=";
+    const NONCE: &str = "611830d3-641a-48f9-8a69-0dcc25d1f4b0";
+
+    #[test]
+    fn test_encrypted_payloads() {
+        let buy = test_api(1).buy_payload("SA20240425000001").unwrap();
+        #[cfg(not(feature = "openssl"))]
+        assert_eq!(
+            buy,
+            json!({ "encKey": ENC_KEY, "encContent": BUY_ENC_CONTENT })
+        );
+        assert_eq!(buy["encContent"], BUY_ENC_CONTENT);
+        assert_eq!(
+            open(&buy),
+            json!({
+                "nonce": NONCE, "amount": 1, "password": "", "paymentType": "Wallet",
+                "reqTimestamp": 1714000000000i64, "saleAggregatorNumber": "SA20240425000001"
+            })
+        );
+
+        let sell = test_api(1).sell_payload(0.5, "1024".into(), 1).unwrap();
+        #[cfg(not(feature = "openssl"))]
+        assert_eq!(
+            sell,
+            json!({ "encKey": ENC_KEY, "encContent": SELL_ENC_CONTENT })
+        );
+        assert_eq!(sell["encContent"], SELL_ENC_CONTENT);
+        assert_eq!(
+            open(&sell),
+            json!({
+                "nonce": NONCE, "amount": 1, "password": "", "price": "0.5",
+                "reqTimestamp": 1714000000000i64, "tokenId": "1024", "coinId": 1, "chainNftId": 12
+            })
+        );
+
+        // 同一客户端的后续请求使用新的 nonce 与会话密钥
+        let api = test_api(1);
+        let first = api.buy_payload("SA20240425000001").unwrap();
+        let second = api.buy_payload("SA20240425000001").unwrap();
+        assert_ne!(first["encContent"], second["encContent"]);
+        assert_ne!(open(&second)["nonce"], NONCE);
+    }
+
+    #[test]
+    fn test_request_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            telemetry::describe_metrics();
+            record_request(
+                endpoint("https://api.cardsahoy.com/api/queryMarketHome"),
+                "ok",
+                Instant::now(),
+            );
+        });
+
+        let metrics = snapshotter.snapshot().into_vec();
+        let (key, _, description, value) = metrics
+            .iter()
+            .find(|(key, ..)| key.key().name() == telemetry::API_REQUESTS)
+            .unwrap();
+        assert!(description.is_some());
+        assert_eq!(*value, DebugValue::Counter(1));
+        let labels: Vec<(&str, &str)> = key
+            .key()
+            .labels()
+            .map(|label| (label.key(), label.value()))
+            .collect();
+        assert!(labels.contains(&("endpoint", "queryMarketHome")));
+
+        let (_, unit, ..) = metrics
+            .iter()
+            .find(|(key, ..)| key.key().name() == telemetry::API_REQUEST_DURATION)
+            .unwrap();
+        assert_eq!(*unit, Some(metrics::Unit::Seconds));
+    }
+}
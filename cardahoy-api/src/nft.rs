@@ -38,6 +38,28 @@ pub enum NftCardColor {
     WhiteGold,
 }
 
+impl NftCardColor {
+    /// The rarity name used by the market filter: Common, Rare, Epic or Legendary.
+    pub fn rarity(&self) -> &'static str {
+        match self {
+            NftCardColor::White | NftCardColor::WhiteGold => "Common",
+            NftCardColor::Blue | NftCardColor::BlueGold => "Rare",
+            NftCardColor::Purple | NftCardColor::PurpleGold => "Epic",
+            NftCardColor::Orange | NftCardColor::OrangeGold => "Legendary",
+        }
+    }
+
+    pub fn is_gold(&self) -> bool {
+        matches!(
+            self,
+            NftCardColor::WhiteGold
+                | NftCardColor::BlueGold
+                | NftCardColor::PurpleGold
+                | NftCardColor::OrangeGold
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum NftSortType {
     PriceAscending = 0,
@@ -829,7 +851,7 @@ fn generate_nft_card_map() -> HashMap<u32, NftCard> {
     card_map
 }
 
-fn get_nft_card_category(id: NftCardId) -> NftCardCategory {
+pub fn get_nft_card_category(id: NftCardId) -> NftCardCategory {
     match id {
         // Animal
         NftCardId::WolfKing => NftCardCategory::Animal,
@@ -1064,7 +1086,7 @@ fn get_nft_card_category(id: NftCardId) -> NftCardCategory {
     }
 }
 
-fn get_nft_card_color(id: NftCardId) -> NftCardColor {
+pub fn get_nft_card_color(id: NftCardId) -> NftCardColor {
     match id {
         // Animal
         NftCardId::WolfKing => NftCardColor::Orange,
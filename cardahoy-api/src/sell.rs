@@ -2,9 +2,9 @@ use crate::nft::NftId;
 
 use super::nft;
 use anyhow::Result;
-use cardahoy_utils as utils;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
+use zeroize::Zeroize;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -27,15 +27,30 @@ pub struct SellNftParams {
     pub chain_nft_id: u32,
 }
 
+impl Drop for SellNftParams {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelSellNftParams {
+    pub nonce: String,
+    pub req_timestamp: i64,
+    pub sale_aggregator_number: String,
+}
+
 impl super::CardsAhoyApi {
     pub async fn query_sell_nft_detail(
         &self,
         nft_id: nft::NftId,
         token_ids: Vec<String>,
+        coin_id: u32,
     ) -> Result<SaleNftPriceRange> {
         let payload = json!({
             "chainNftId": nft_id as u32,
-            "coinId": 1,
+            "coinId": coin_id,
             "tokenIds": token_ids
         });
 
@@ -48,23 +63,27 @@ impl super::CardsAhoyApi {
         Ok(result)
     }
 
-    pub async fn ground_nft_asset(&self, price: f64, token_id: String) -> Result<String> {
+    pub(crate) fn sell_payload(&self, price: f64, token_id: String, coin_id: u32) -> Result<Value> {
         let params = SellNftParams {
-            nonce: uuid::Uuid::new_v4().to_string(),
+            nonce: self.nonce(),
             amount: 1,
             password: String::from(""),
             price: format!("{}", price),
-            req_timestamp: utils::timestamp(),
+            req_timestamp: self.clock.timestamp(),
             token_id,
-            coin_id: 1,
+            coin_id,
             chain_nft_id: NftId::Cards as u32,
         };
+        self.seal(&params)
+    }
 
-        let (enc_key, enc_content) = self.encrypt(params)?;
-        let payload = json!({
-            "encContent": enc_content,
-            "encKey": enc_key
-        });
+    pub async fn ground_nft_asset(
+        &self,
+        price: f64,
+        token_id: String,
+        coin_id: u32,
+    ) -> Result<String> {
+        let payload = self.sell_payload(price, token_id, coin_id)?;
         tracing::debug!("[API] payload: {:?} ", payload);
 
         let result = self
@@ -76,4 +95,35 @@ impl super::CardsAhoyApi {
 
         Ok(result)
     }
+
+    /// Withdraws one of the account's active listings.
+    ///
+    /// The payload is encrypted the same way as `ground_nft_asset`. Use `query_user_sale_list` to find the `sale_aggregator_number` of the listing.
+    ///
+    /// # Arguments
+    ///
+    /// * `sale_aggregator_number` - The sale aggregator number of the listing to withdraw.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` type containing the server response on success, or an error if the request fails.
+    pub async fn cancel_ground_nft_asset(&self, sale_aggregator_number: &str) -> Result<String> {
+        let params = CancelSellNftParams {
+            nonce: self.nonce(),
+            req_timestamp: self.clock.timestamp(),
+            sale_aggregator_number: sale_aggregator_number.into(),
+        };
+
+        let payload = self.seal(&params)?;
+        tracing::debug!("[API] payload: {:?} ", payload);
+
+        let result = self
+            .post::<String>(
+                "https://game.metalist.io/api/marketOperate/cancelGroundNFTAsset",
+                payload,
+            )
+            .await?;
+
+        Ok(result)
+    }
 }
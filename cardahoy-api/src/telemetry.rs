@@ -0,0 +1,23 @@
+// 接口请求的指标名，scanner 的 telemetry 模块重新导出
+pub const API_REQUESTS: &str = "cardahoy_api_requests_total";
+pub const API_RETRIES: &str = "cardahoy_api_retries_total";
+pub const API_HTML_PAGES: &str = "cardahoy_api_html_pages_total";
+pub const API_REQUEST_DURATION: &str = "cardahoy_api_request_duration_seconds";
+
+/// Registers help text for the metrics recorded by the API client.
+pub fn describe_metrics() {
+    metrics::describe_counter!(
+        API_REQUESTS,
+        "API requests by endpoint and outcome (ok, api_error, http_error, html_503, decode_error, transport_error)"
+    );
+    metrics::describe_counter!(API_RETRIES, "API request retries by endpoint");
+    metrics::describe_counter!(
+        API_HTML_PAGES,
+        "HTML 503 pages returned with status 200, by endpoint"
+    );
+    metrics::describe_histogram!(
+        API_REQUEST_DURATION,
+        metrics::Unit::Seconds,
+        "API request latency by endpoint"
+    );
+}
@@ -0,0 +1,62 @@
+use openssl::{
+    pkey::{Private, Public},
+    rsa::{Padding, Rsa},
+};
+use zeroize::Zeroizing;
+
+use crate::{CryptoError, RandomSource, Result};
+
+// 启用 openssl 特性时使用的系统 OpenSSL 实现
+#[derive(Clone, Debug)]
+pub struct PublicKey(Rsa<Public>);
+
+impl PublicKey {
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        let key = Rsa::public_key_from_pem(pem.as_bytes())
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        Ok(Self(key))
+    }
+
+    // OpenSSL 用自己的随机数生成填充，忽略 rng，密文不可复现
+    pub fn encrypt(&self, message: &[u8], _rng: &dyn RandomSource) -> Result<Vec<u8>> {
+        let mut encrypted = vec![0u8; self.0.size() as usize];
+        let len = self
+            .0
+            .public_encrypt(message, &mut encrypted, Padding::PKCS1)
+            .map_err(|e| CryptoError::Encrypt(e.to_string()))?;
+        encrypted.truncate(len);
+        Ok(encrypted)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PrivateKey(Rsa<Private>);
+
+impl PrivateKey {
+    // PKCS#1 与 PKCS#8 格式都可以解析
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        let key = Rsa::private_key_from_pem(pem.as_bytes())
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        Ok(Self(key))
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey> {
+        let key = self
+            .0
+            .n()
+            .to_owned()
+            .and_then(|n| Rsa::from_public_components(n, self.0.e().to_owned()?))
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        Ok(PublicKey(key))
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let mut decrypted = Zeroizing::new(vec![0u8; self.0.size() as usize]);
+        let len = self
+            .0
+            .private_decrypt(ciphertext, &mut decrypted, Padding::PKCS1)
+            .map_err(|_| CryptoError::Decrypt)?;
+        decrypted.truncate(len);
+        Ok(decrypted)
+    }
+}
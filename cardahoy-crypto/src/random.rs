@@ -0,0 +1,76 @@
+use rand::{
+    distributions::Alphanumeric, rngs::StdRng, thread_rng, CryptoRng, Rng, RngCore, SeedableRng,
+};
+use std::sync::Mutex;
+
+/// Where session keys and request nonces come from.
+///
+/// [`OsRandom`] is used in production; [`SeededRandom`] makes the encrypted
+/// requests reproducible in tests.
+pub trait RandomSource: Send + Sync {
+    fn fill_bytes(&self, dest: &mut [u8]);
+
+    /// An alphanumeric key of `length` characters.
+    fn random_key(&self, length: usize) -> String {
+        Source(self)
+            .sample_iter(&Alphanumeric)
+            .take(length)
+            .map(char::from)
+            .collect()
+    }
+}
+
+/// The thread-local CSPRNG.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        thread_rng().fill_bytes(dest)
+    }
+}
+
+/// A deterministic generator for tests; the same seed yields the same keys.
+#[derive(Debug)]
+pub struct SeededRandom(Mutex<StdRng>);
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self(Mutex::new(StdRng::seed_from_u64(seed)))
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        self.0.lock().unwrap().fill_bytes(dest)
+    }
+}
+
+// 把 RandomSource 适配为 rand 的 RngCore，以便使用 rand 的分布与 rsa 的填充
+pub(crate) struct Source<'a, R: RandomSource + ?Sized>(pub(crate) &'a R);
+
+// RandomSource 的实现约定为密码学安全（测试用的 SeededRandom 除外）
+impl<R: RandomSource + ?Sized> CryptoRng for Source<'_, R> {}
+
+impl<R: RandomSource + ?Sized> RngCore for Source<'_, R> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.0.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.0.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -1,8 +1,21 @@
+use cardahoy_utils as utils;
 use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Config file; any key can also be set with a CARDAHOY_* environment variable
+    #[arg(long, global = true, default_value = utils::DEFAULT_CONFIG_PATH)]
+    pub config: String,
+    /// Use this account from [profiles] in the config; the daemon runs every profile when unset
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// Serve Prometheus metrics on this address, overrides metrics_addr in config.toml
+    #[arg(long, global = true)]
+    pub metrics_addr: Option<String>,
+    /// Serve the control API on this address while running the daemon, overrides control_addr in config.toml
+    #[arg(long, global = true)]
+    pub control_addr: Option<String>,
     #[clap(subcommand)]
     pub command: Option<Commands>,
 }
@@ -15,6 +28,112 @@ pub enum Commands {
     },
     Analyze,
     AnalyzeRealtime,
+    /// Run the scheduled strategies until SIGINT/SIGTERM, see [daemon] in config.toml.
+    /// With --control-addr, serves /status, /pause, /resume, /params and /scan
+    Daemon,
+    /// Live market dashboard: floors, quantities, volumes and gold spreads per card
+    Tui {
+        /// Also run the scheduled strategies from [daemon] in config.toml
+        #[arg(long)]
+        trade: bool,
+        /// Seconds between market refreshes
+        #[arg(long, default_value_t = 10)]
+        refresh_secs: u64,
+    },
+    Report {
+        #[clap(subcommand)]
+        command: ReportCommands,
+    },
+    /// Replay recorded market snapshots through the buy strategies
+    Backtest(Backtest),
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Inspect the encrypted bodies of buy, sell and login requests
+    Crypto {
+        #[clap(subcommand)]
+        command: CryptoCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CryptoCommands {
+    /// Encrypt a JSON body into {"encKey", "encContent"} the same way the client does
+    Seal {
+        /// JSON file, or - for stdin; encrypted as given, so pass it compact and in field order
+        #[arg(default_value = "-")]
+        input: String,
+        /// Session key to use instead of a random one, to reproduce a captured encContent
+        #[arg(long)]
+        session_key: Option<String>,
+        /// RSA public key PEM, defaults to the key the client would use with --config
+        #[arg(long)]
+        public_key: Option<String>,
+    },
+    /// Decrypt encContent with a known AES session key
+    Decrypt {
+        /// 16-character session key
+        #[arg(long)]
+        key: String,
+        /// Captured request body or bare encContent, from a file or - for stdin
+        #[arg(default_value = "-")]
+        input: String,
+    },
+    /// Open a captured request body with the RSA private key of a mock environment
+    Open {
+        /// RSA private key PEM (PKCS#1 or PKCS#8)
+        #[arg(long)]
+        private_key: String,
+        /// Captured request body, from a file or - for stdin
+        #[arg(default_value = "-")]
+        input: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Validate the config file, RSA public key and neutral.csv without starting the scanner
+    Check {
+        /// Also query the balance to confirm the session is accepted
+        #[arg(long)]
+        ping: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReportCommands {
+    /// Realized and unrealized P&L per strategy from the trade ledger
+    Pnl,
+    /// Market changes between consecutive recorded snapshots
+    Diff {
+        #[arg(long, default_value = "history.jsonl")]
+        history: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Backtest {
+    /// Snapshot file written when `history_path` is set in config.toml
+    #[arg(long, default_value = "history.jsonl")]
+    pub history: String,
+    /// Strategies to replay, all by default
+    #[arg(long, value_delimiter = ',')]
+    pub strategy: Vec<String>,
+    /// Probability that a cheap listing is bought by someone else first
+    #[arg(long, default_value_t = 0.3)]
+    pub miss_rate: f64,
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    #[arg(long)]
+    pub gold_ratio: Option<f64>,
+    #[arg(long)]
+    pub five_avg_ratio: Option<f64>,
+    #[arg(long)]
+    pub max_exp: Option<u32>,
+    /// Required discount below modelled fair value for the fair_value strategy
+    #[arg(long)]
+    pub fair_value_margin: Option<f64>,
 }
 
 #[derive(Args, Debug)]
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use cardahoy_scanner::{
+    control::{self, ParamsUpdate, Status},
+    store::Store,
+    strategy::StrategyParams,
+};
+use std::{net::SocketAddr, sync::Arc};
+
+type ApiResult<T> = std::result::Result<Json<T>, (StatusCode, String)>;
+
+/// Routes of the local control API.
+///
+/// - `GET /status`: tasks, pause state, last cycle, balance, recent orders
+/// - `POST /pause`, `POST /resume`: stop or restart buying
+/// - `GET /params`, `POST /params`: read or change strategy thresholds
+/// - `POST /scan`: run every scheduled task now
+///
+/// With `control_token` configured, every request needs an
+/// `Authorization: Bearer <token>` header.
+pub fn router(store: Arc<Store>) -> Router {
+    let router = Router::new()
+        .route("/status", get(status))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/params", get(params).post(update_params))
+        .route("/scan", post(scan));
+    let router = match store.config.control_token.clone() {
+        Some(token) if !token.is_empty() => router.layer(middleware::from_fn_with_state(
+            Arc::new(token),
+            require_token,
+        )),
+        _ => router,
+    };
+    router.with_state(store)
+}
+
+pub async fn serve(addr: SocketAddr, store: Arc<Store>) -> Result<()> {
+    control::check_bind(addr, store.config.control_token.as_deref())?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("[控制]: 监听 http://{}", addr);
+    axum::serve(listener, router(store)).await?;
+    Ok(())
+}
+
+async fn require_token(
+    State(token): State<Arc<String>>,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, StatusCode> {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !control::authorized(header, &token) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+async fn status(State(store): State<Arc<Store>>) -> Json<Status> {
+    Json(store.status())
+}
+
+async fn pause(State(store): State<Arc<Store>>) -> Json<Status> {
+    store.control.set_paused(true);
+    Json(store.status())
+}
+
+async fn resume(State(store): State<Arc<Store>>) -> Json<Status> {
+    store.control.set_paused(false);
+    Json(store.status())
+}
+
+async fn params(State(store): State<Arc<Store>>) -> Json<StrategyParams> {
+    Json(store.params())
+}
+
+async fn update_params(
+    State(store): State<Arc<Store>>,
+    Json(update): Json<ParamsUpdate>,
+) -> ApiResult<StrategyParams> {
+    store
+        .update_params(&update)
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn scan(State(store): State<Arc<Store>>) -> StatusCode {
+    store.control.request_scan();
+    StatusCode::ACCEPTED
+}
@@ -1,22 +1,29 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 #[warn(dead_code)]
 use cardahoy_api as api;
+use cardahoy_crypto as crypto;
 use cardahoy_scanner as scanner;
+use cardahoy_utils as utils;
 use clap::Parser;
-use std::env;
+use std::{env, io::Write, net::SocketAddr, sync::Arc};
 use tracing_appender::rolling;
 use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
 pub mod commands;
-use crate::commands::{Cli, Commands};
+pub mod control;
+pub mod tui;
+use crate::commands::{Backtest, Cli, Commands, ConfigCommands, CryptoCommands, ReportCommands};
 
-fn setup_logging() {
+// tui 模式下终端由界面占用，日志改为写入 buffer 显示在界面中
+fn setup_logging(tui: Option<tui::LogBuffer>) {
     let file_appender = rolling::daily("./logs", "scanner");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
     let filter = EnvFilter::new("info");
 
     // 设置订阅者，用于日志输出到控制台
-    let stdout_layer = fmt::layer().with_writer(std::io::stdout);
+    let stdout_layer = tui
+        .is_none()
+        .then(|| fmt::layer().with_writer(std::io::stdout));
     // 设置订阅者，用于日志记录到文件
     let file_layer = fmt::layer().with_writer(non_blocking).with_ansi(false);
 
@@ -24,26 +31,57 @@ fn setup_logging() {
     let subscriber = tracing_subscriber::registry()
         .with(filter) // 设置过滤器
         .with(stdout_layer) // 输出到控制台
-        .with(file_layer); // 记录到文件
-                           // .init()
+        .with(file_layer) // 记录到文件
+        .with(tui); // 显示在界面中
+                    // .init()
 
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 }
 
+// 启动 Prometheus 指标服务，提供 /metrics
+fn setup_metrics(addr: &str) -> Result<()> {
+    let addr: std::net::SocketAddr = addr.parse()?;
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .install()?;
+    scanner::telemetry::describe_metrics();
+    tracing::info!("[指标]: 监听 http://{}/metrics", addr);
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    setup_logging();
-    tracing::info!("日志系统已初始化，开始记录日志");
-    api::nft::init_nft_card_map();
-
     let raw_args = env::args_os().collect::<Vec<_>>();
     let cli = Cli::parse_from(raw_args);
+
+    let logs = matches!(cli.command, Some(Commands::Tui { .. })).then(tui::LogBuffer::default);
+    setup_logging(logs.clone());
+    tracing::info!("日志系统已初始化，开始记录日志");
+    api::nft::init_nft_card_map();
+    // 回测等离线命令不需要配置文件
+    let config = match &cli.command {
+        Some(Commands::Backtest(_))
+        | Some(Commands::Report {
+            command: ReportCommands::Diff { .. },
+        })
+        | Some(Commands::Config { .. })
+        | Some(Commands::Crypto { .. })
+        | None => None,
+        Some(_) => Some(load_config(&cli.config, cli.profile.as_deref())?),
+    };
+    let metrics_addr = cli
+        .metrics_addr
+        .clone()
+        .or_else(|| config.as_ref()?.metrics_addr.clone());
+    if let Some(addr) = metrics_addr {
+        setup_metrics(&addr)?;
+    }
     match &cli.command {
         Some(Commands::Analyze) => {
-            scanner::script::get_all_card_deal_trend().await?;
+            scanner::script::get_all_card_deal_trend(&required(config)?).await?;
         }
         Some(Commands::ScanMarket { card }) => {
-            let s = scanner::store::Store::new().expect("Store init error");
+            let s = scanner::store::Store::new(required(config)?)?;
             loop {
                 // or use filter_scan
                 let result = s.custom_scan_full().await;
@@ -51,15 +89,295 @@ async fn main() -> Result<()> {
                 if let Err(e) = result {
                     println!("{:#?}", e);
                 }
+                if let Err(e) = s.sell_cycle().await {
+                    println!("{:#?}", e);
+                }
                 // tokio::time::sleep(std::time::Duration::from_secs(1)).await;
             }
         }
+        Some(Commands::Daemon) => {
+            let config = required(config)?;
+            let shutdown = scanner::daemon::shutdown_signal();
+            // 配置了多个账户且未指定 --profile 时，所有账户同时运行并共用行情
+            if !config.profiles.is_empty() {
+                let stores = scanner::store::Store::profiles(&config)?;
+                if cli.control_addr.is_some() || config.control_addr.is_some() {
+                    tracing::warn!("[控制]: 多账户运行时不提供控制接口, 请用 --profile 指定账户");
+                }
+                return scanner::daemon::run_profiles(&stores, shutdown).await;
+            }
+
+            let s = Arc::new(scanner::store::Store::new(config)?);
+            let control_addr = cli
+                .control_addr
+                .clone()
+                .or_else(|| s.config.control_addr.clone());
+            if let Some(addr) = control_addr {
+                let addr: SocketAddr = addr.parse()?;
+                // 在启动守护进程前拒绝不安全的监听地址
+                scanner::control::check_bind(addr, s.config.control_token.as_deref())?;
+                let store = s.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = control::serve(addr, store).await {
+                        tracing::error!("[控制]: 控制接口退出, {:?}", e);
+                    }
+                });
+            }
+            scanner::daemon::Daemon::new(&s, s.config.daemon.clone())?
+                .run(shutdown)
+                .await?;
+        }
+        Some(Commands::Tui {
+            trade,
+            refresh_secs,
+        }) => {
+            let s = Arc::new(scanner::store::Store::new(required(config)?)?);
+            let refresh = std::time::Duration::from_secs(*refresh_secs);
+            tui::run(s, logs.unwrap_or_default(), refresh, *trade).await?;
+        }
         Some(Commands::AnalyzeRealtime) => {
             tracing::info!("开始获取实时数据");
-            scanner::script::get_all_card_realtime().await?;
+            scanner::script::get_all_card_realtime(&required(config)?).await?;
+        }
+        Some(Commands::Report { command }) => match command {
+            ReportCommands::Pnl => {
+                let s = scanner::store::Store::new(required(config)?)?;
+                s.report_pnl().await?;
+            }
+            ReportCommands::Diff { history } => {
+                use scanner::{history::HistoryStore, snapshot};
+
+                let history = HistoryStore::new(history).load()?;
+                let snapshots = snapshot::MarketSnapshot::replay(&history);
+                for pair in snapshots.windows(2) {
+                    snapshot::print_diff(&snapshot::diff(&pair[0], &pair[1]));
+                }
+            }
+        },
+        Some(Commands::Backtest(args)) => backtest(args)?,
+        Some(Commands::Config { command }) => match command {
+            ConfigCommands::Check { ping } => {
+                check_config(&cli.config, cli.profile.as_deref(), *ping).await?
+            }
+        },
+        Some(Commands::Crypto { command }) => {
+            crypto_command(&cli, command, &mut std::io::stdout().lock())?
         }
         None => {}
     }
 
     Ok(())
 }
+
+// 指定 --profile 时使用该账户的配置
+fn load_config(path: &str, profile: Option<&str>) -> Result<utils::Config> {
+    let config = utils::Config::load(path)?;
+    match profile {
+        Some(name) => config.profile(name),
+        None => Ok(config),
+    }
+}
+
+// 需要配置的命令在启动时已经加载
+fn required(config: Option<utils::Config>) -> Result<utils::Config> {
+    config.ok_or_else(|| anyhow::anyhow!("配置未加载"))
+}
+
+async fn check_config(path: &str, profile: Option<&str>, ping: bool) -> Result<()> {
+    use scanner::check::{self, Severity};
+
+    let config = load_config(path, profile)?;
+    let mut diagnostics = check::check_config(&config, std::path::Path::new("neutral.csv"));
+    let headers_ok = diagnostics
+        .iter()
+        .all(|d| d.severity != Severity::Error || !check::HEADERS.contains(&d.item.as_str()));
+    if ping && headers_ok {
+        let api = api::CardsAhoyApi::new(&config)?;
+        diagnostics.push(check::check_session(&api).await);
+    }
+    check::print_diagnostics(&diagnostics);
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("{} 中有{}处错误", path, errors);
+    }
+    Ok(())
+}
+
+// 读取文件内容，- 表示标准输入
+fn read_input(input: &str) -> Result<String> {
+    use std::io::Read;
+
+    if input == "-" {
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents)?;
+        return Ok(contents);
+    }
+    std::fs::read_to_string(input).with_context(|| format!("无法读取 {}", input))
+}
+
+// 明文原样输出，不重新排序字段，也不要求是 JSON
+fn write_plaintext(out: &mut impl Write, plaintext: &[u8]) -> Result<()> {
+    out.write_all(plaintext)?;
+    if !plaintext.ends_with(b"\n") {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn crypto_command(cli: &Cli, command: &CryptoCommands, out: &mut impl Write) -> Result<()> {
+    match command {
+        CryptoCommands::Seal {
+            input,
+            session_key,
+            public_key,
+        } => {
+            // 原样加密，保留字段顺序与空白，便于与抓包的 encContent 比对
+            let payload: Box<serde_json::value::RawValue> =
+                serde_json::from_str(read_input(input)?.trim()).context("请求体不是有效的 JSON")?;
+            // 与客户端相同：--public-key 优先，其次是配置中的公钥，没有配置文件时用内置公钥
+            let public_key = match public_key {
+                Some(path) => crypto::PublicKey::from_pem(&read_input(path)?)?,
+                None if std::path::Path::new(&cli.config).exists() => {
+                    api::load_public_key(&load_config(&cli.config, cli.profile.as_deref())?)?
+                }
+                None => crypto::PublicKey::from_pem(api::DEFAULT_PUBLIC_KEY)?,
+            };
+            let envelope = match session_key {
+                Some(key) => {
+                    crypto::EncryptedEnvelope::seal_with_session_key(&payload, key, &public_key)?
+                }
+                None => crypto::EncryptedEnvelope::seal(&payload, &public_key)?,
+            };
+            writeln!(out, "{}", serde_json::to_string(&envelope)?)?;
+        }
+        CryptoCommands::Decrypt { key, input } => {
+            let input = read_input(input)?;
+            let enc_content = match serde_json::from_str::<crypto::EncryptedEnvelope>(&input) {
+                Ok(envelope) => envelope.enc_content,
+                Err(_) => input.trim().to_string(),
+            };
+            let plaintext = crypto::aes_ecb_decrypt(&crypto::base64_decode(&enc_content)?, key)?;
+            write_plaintext(out, &plaintext)?;
+        }
+        CryptoCommands::Open { private_key, input } => {
+            let private_key = crypto::PrivateKey::from_pem(&read_input(private_key)?)?;
+            let envelope: crypto::EncryptedEnvelope = serde_json::from_str(&read_input(input)?)
+                .context("请求体应包含 encKey 与 encContent")?;
+            write_plaintext(out, &envelope.decrypt(&private_key)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn backtest(args: &Backtest) -> Result<()> {
+    use scanner::{backtest, history::HistoryStore, strategy::Strategy};
+
+    let strategies = if args.strategy.is_empty() {
+        Strategy::all()
+    } else {
+        args.strategy
+            .iter()
+            .map(|name| {
+                Strategy::from_name(name).ok_or_else(|| anyhow::anyhow!("未知策略: {}", name))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let mut config = backtest::BacktestConfig {
+        strategies,
+        miss_rate: args.miss_rate,
+        seed: args.seed,
+        ..Default::default()
+    };
+    if let Some(ratio) = args.gold_ratio {
+        config.params.gold_ratio = ratio;
+    }
+    if let Some(ratio) = args.five_avg_ratio {
+        config.params.five_avg_ratio = ratio;
+    }
+    if let Some(max_exp) = args.max_exp {
+        config.params.max_exp = max_exp;
+    }
+    if let Some(margin) = args.fair_value_margin {
+        config.params.fair_value_margin = margin;
+    }
+
+    let snapshots = HistoryStore::new(&args.history).load()?;
+    let prices = scanner::store::read_csv("neutral.csv")?;
+    tracing::info!("[回测]: 共{}条行情快照", snapshots.len());
+
+    let report = backtest::run(&config, &prices, &snapshots);
+    backtest::print_report(&report);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../cardahoy-crypto/testdata/public.pem"
+    );
+    const PRIVATE_KEY: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../cardahoy-crypto/testdata/private.pem"
+    );
+
+    fn run(args: &[&str]) -> Vec<u8> {
+        let cli = Cli::parse_from(
+            [
+                &["cardahoy-scanner-cli", "--config", "missing.toml", "crypto"],
+                args,
+            ]
+            .concat(),
+        );
+        let Some(Commands::Crypto { command }) = &cli.command else {
+            panic!("not a crypto command");
+        };
+        let mut out = Vec::new();
+        crypto_command(&cli, command, &mut out).unwrap();
+        out
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = env::temp_dir().join(format!("{}-{}", name, utils::timestamp()));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into()
+    }
+
+    #[test]
+    fn test_seal_open_round_trip() {
+        // 字段顺序与空白原样保留
+        let body = r#"{"saleAggregatorNumber":"SA1", "amount":1,"nonce":"n"}"#;
+        let input = temp_file("body.json", body.as_bytes());
+        let sealed = run(&[
+            "seal",
+            &input,
+            "--session-key",
+            "4krtPt4BBRNN59bT",
+            "--public-key",
+            PUBLIC_KEY,
+        ]);
+        let envelope = temp_file("envelope.json", &sealed);
+
+        let opened = run(&["open", "--private-key", PRIVATE_KEY, &envelope]);
+        assert_eq!(String::from_utf8(opened).unwrap(), format!("{}\n", body));
+        let decrypted = run(&["decrypt", "--key", "4krtPt4BBRNN59bT", &envelope]);
+        assert_eq!(String::from_utf8(decrypted).unwrap(), format!("{}\n", body));
+
+        // 只有 encContent 时同样可以解密
+        let sealed: crypto::EncryptedEnvelope = serde_json::from_slice(&sealed).unwrap();
+        let content = temp_file("content.txt", sealed.enc_content.as_bytes());
+        let decrypted = run(&["decrypt", "--key", "4krtPt4BBRNN59bT", &content]);
+        assert_eq!(String::from_utf8(decrypted).unwrap(), format!("{}\n", body));
+
+        for path in [input, envelope, content] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use anyhow::Result;
+use cardahoy_api::{market_home::MarketHomeResponse, market_secondary::MarketSecondaryResponse};
+use cardahoy_scanner::{
+    daemon::{Daemon, Shutdown},
+    dashboard::{CardRow, Dashboard},
+    store::Store,
+    strategy::unit_price,
+};
+use cardahoy_utils as utils;
+use chrono::{Local, TimeZone};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, List, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context, Layer};
+
+// 保留的日志行数
+const LOG_LINES: usize = 200;
+// 最近交易的显示数量
+const RECENT_ORDERS: usize = 20;
+
+/// Keeps the latest scanner log messages for the decisions pane. Installed
+/// in place of the stdout logger while the TUI owns the terminal.
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<String>>>);
+
+impl LogBuffer {
+    fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if !event.metadata().target().starts_with("cardahoy") {
+            return;
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        let mut lines = self.0.lock().unwrap();
+        lines.push_back(format!("{} {}", Local::now().format("%H:%M:%S"), visitor.0));
+        if lines.len() > LOG_LINES {
+            lines.pop_front();
+        }
+    }
+}
+
+enum View {
+    Market,
+    // 单张卡牌的挂单列表
+    Listings {
+        card_id: u32,
+        name: String,
+        home: MarketHomeResponse,
+    },
+}
+
+// 后台查询的结果，由界面循环取出后更新
+enum Fetched {
+    Market(Result<MarketSecondaryResponse>),
+    Listings {
+        card_id: u32,
+        name: String,
+        // 由 Enter 打开，而不是挂单页的定时刷新
+        open: bool,
+        result: Result<MarketHomeResponse>,
+    },
+}
+
+struct App {
+    store: Arc<Store>,
+    logs: LogBuffer,
+    refresh: Duration,
+    dashboard: Dashboard,
+    table: TableState,
+    view: View,
+    error: Option<String>,
+    last_refresh: Option<Instant>,
+    // 查询在后台任务中进行，界面循环不等待网络请求
+    fetched_tx: mpsc::UnboundedSender<Fetched>,
+    fetched_rx: mpsc::UnboundedReceiver<Fetched>,
+    refreshing: Option<JoinHandle<()>>,
+}
+
+/// Runs the market dashboard until `q`. With `trade`, the scheduled daemon
+/// runs alongside and stops between tasks when the dashboard exits.
+pub async fn run(store: Arc<Store>, logs: LogBuffer, refresh: Duration, trade: bool) -> Result<()> {
+    let (shutdown_tx, shutdown_rx) = watch::channel(Shutdown::Running);
+    let daemon = async {
+        if trade {
+            Daemon::new(&store, store.config.daemon.clone())?
+                .run(shutdown_rx)
+                .await
+        } else {
+            Ok(())
+        }
+    };
+    let ui = async {
+        let mut terminal = ratatui::init();
+        let (fetched_tx, fetched_rx) = mpsc::unbounded_channel();
+        let mut app = App {
+            store: store.clone(),
+            logs,
+            refresh,
+            dashboard: Dashboard::default(),
+            table: TableState::default().with_selected(0),
+            view: View::Market,
+            error: None,
+            last_refresh: None,
+            fetched_tx,
+            fetched_rx,
+            refreshing: None,
+        };
+        let result = app.run(&mut terminal).await;
+        ratatui::restore();
+        let _ = shutdown_tx.send(Shutdown::Requested);
+        result
+    };
+    let (daemon, ui) = tokio::join!(daemon, ui);
+    ui.and(daemon)
+}
+
+impl App {
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            if self
+                .last_refresh
+                .is_none_or(|last| last.elapsed() >= self.refresh)
+            {
+                self.refresh();
+            }
+            while let Ok(fetched) = self.fetched_rx.try_recv() {
+                self.apply(fetched);
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            // 非阻塞读取按键，让同时运行的守护进程继续执行
+            if !event::poll(Duration::ZERO)? {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl_c =
+                key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                _ if ctrl_c => return Ok(()),
+                KeyCode::Esc => self.view = View::Market,
+                KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+                KeyCode::Char('s') => self.dashboard.sort = self.dashboard.sort.next(),
+                KeyCode::Char('o') => self.dashboard.descending = !self.dashboard.descending,
+                KeyCode::Char('f') => self.dashboard.cycle_faction(),
+                KeyCode::Char('y') => self.dashboard.cycle_rarity(),
+                KeyCode::Char('u') => self.last_refresh = None,
+                KeyCode::Enter => self.open_selected(),
+                _ => {}
+            }
+        }
+    }
+
+    // 上一次刷新还没完成时不重复发起
+    fn refresh(&mut self) {
+        if self
+            .refreshing
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+        {
+            return;
+        }
+        self.last_refresh = Some(Instant::now());
+        let (store, tx) = (self.store.clone(), self.fetched_tx.clone());
+        let listings = match &self.view {
+            View::Market => None,
+            View::Listings { card_id, name, .. } => Some((*card_id, name.clone())),
+        };
+        self.refreshing = Some(tokio::spawn(async move {
+            let market = store.scan_all_cards().await;
+            let ok = market.is_ok();
+            let _ = tx.send(Fetched::Market(market));
+            // 挂单页同时刷新市场概览
+            if let (true, Some((card_id, name))) = (ok, listings) {
+                let result = store.scan_card_home(card_id).await;
+                let _ = tx.send(Fetched::Listings {
+                    card_id,
+                    name,
+                    open: false,
+                    result,
+                });
+            }
+        }));
+    }
+
+    fn open_selected(&mut self) {
+        if !matches!(self.view, View::Market) {
+            return;
+        }
+        let Some(row) = self.table.selected().and_then(|index| {
+            self.dashboard
+                .visible()
+                .get(index)
+                .map(|row| (*row).clone())
+        }) else {
+            return;
+        };
+        let (store, tx) = (self.store.clone(), self.fetched_tx.clone());
+        tokio::spawn(async move {
+            let result = store.scan_card_home(row.card_id).await;
+            let _ = tx.send(Fetched::Listings {
+                card_id: row.card_id,
+                name: row.name,
+                open: true,
+                result,
+            });
+        });
+    }
+
+    fn apply(&mut self, fetched: Fetched) {
+        match fetched {
+            Fetched::Market(Ok(resp)) => {
+                self.dashboard.update(utils::timestamp(), &resp);
+                self.error = None;
+            }
+            Fetched::Listings {
+                card_id,
+                name,
+                open,
+                result: Ok(resp),
+            } => match &mut self.view {
+                // 查询期间已按 Esc 返回或切换到其他卡牌时丢弃结果
+                View::Market if open => {
+                    self.view = View::Listings {
+                        card_id,
+                        name,
+                        home: resp,
+                    };
+                    self.error = None;
+                }
+                View::Listings {
+                    card_id: current,
+                    home,
+                    ..
+                } if *current == card_id && !open => *home = resp,
+                _ => {}
+            },
+            Fetched::Market(Err(e)) | Fetched::Listings { result: Err(e), .. } => {
+                self.error = Some(e.to_string())
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, main, bottom] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Min(8),
+            Constraint::Length(12),
+        ])
+        .areas(frame.area());
+        let [decisions, orders] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(bottom);
+
+        self.draw_header(frame, header);
+        match &self.view {
+            View::Market => self.draw_market(frame, main),
+            View::Listings { name, home, .. } => draw_listings(frame, main, name, home),
+        }
+        self.draw_decisions(frame, decisions);
+        self.draw_orders(frame, orders);
+    }
+
+    fn draw_header(&self, frame: &mut Frame, area: Rect) {
+        let updated = self
+            .dashboard
+            .updated
+            .map(format_time)
+            .unwrap_or_else(|| "-".into());
+        let status = format!(
+            "排序: {}{}  阵营: {}  稀有度: {}  更新: {}{}",
+            self.dashboard.sort.label(),
+            if self.dashboard.descending {
+                "↓"
+            } else {
+                "↑"
+            },
+            self.dashboard.faction.unwrap_or("全部"),
+            self.dashboard.rarity.unwrap_or("全部"),
+            updated,
+            if self.store.control.is_paused() {
+                "  已暂停购买"
+            } else {
+                ""
+            }
+        );
+        let help = match &self.error {
+            Some(error) => Line::styled(error.clone(), Style::default().fg(Color::Red)),
+            None => Line::styled(
+                "q 退出  ↑↓ 选择  Enter 挂单  Esc 返回  s 排序  o 升降序  f 阵营  y 稀有度  u 刷新",
+                Style::default().fg(Color::DarkGray),
+            ),
+        };
+        frame.render_widget(Paragraph::new(vec![Line::from(status), help]), area);
+    }
+
+    fn draw_market(&mut self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .dashboard
+            .visible()
+            .into_iter()
+            .map(market_row)
+            .collect();
+        let count = rows.len();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(16),
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(9),
+                Constraint::Length(7),
+            ],
+        )
+        .header(
+            Row::new([
+                "卡牌",
+                "阵营",
+                "稀有度",
+                "底价",
+                "在售",
+                "成交",
+                "涨跌",
+                "金/普",
+            ])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title(format!("市场 ({})", count)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_decisions(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let lines = self.logs.lines();
+        let lines: Vec<String> = lines.into_iter().rev().take(height).rev().collect();
+        frame.render_widget(
+            List::new(lines).block(Block::bordered().title("策略日志")),
+            area,
+        );
+    }
+
+    fn draw_orders(&self, frame: &mut Frame, area: Rect) {
+        let trades = self.store.ledger.trades();
+        let rows: Vec<Row> = trades
+            .iter()
+            .rev()
+            .take(RECENT_ORDERS)
+            .map(|trade| {
+                Row::new([
+                    format_time(trade.timestamp),
+                    format!("{:?}", trade.side),
+                    trade.nft_name.clone(),
+                    format!("{:.4}", trade.price),
+                    trade.strategy.clone(),
+                ])
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(4),
+                Constraint::Min(12),
+                Constraint::Length(9),
+                Constraint::Length(12),
+            ],
+        )
+        .block(
+            Block::bordered().title(format!("交易 (挂单{})", self.store.seller.listings().len())),
+        );
+        frame.render_widget(table, area);
+    }
+}
+
+fn market_row(row: &CardRow) -> Row<'static> {
+    let change_style = match row.change {
+        Some(change) if change > 0.0 => Style::default().fg(Color::Green),
+        Some(change) if change < 0.0 => Style::default().fg(Color::Red),
+        _ => Style::default(),
+    };
+    let format = |value: Option<f64>, f: fn(f64) -> String| value.map(f).unwrap_or_default();
+    Row::new([
+        ratatui::text::Text::from(row.name.clone()),
+        row.faction.clone().into(),
+        row.rarity.into(),
+        format(row.floor, |v| format!("{:.4}", v)).into(),
+        row.quantity.to_string().into(),
+        row.volume.to_string().into(),
+        ratatui::text::Text::styled(
+            format(row.change, |v| format!("{:+.2}%", v * 100.0)),
+            change_style,
+        ),
+        format(row.gold_spread, |v| format!("{:.2}", v)).into(),
+    ])
+}
+
+fn draw_listings(frame: &mut Frame, area: Rect, name: &str, home: &MarketHomeResponse) {
+    let rows: Vec<Row> = home
+        .list
+        .iter()
+        .map(|ci| {
+            Row::new([
+                ci.nft_name.clone(),
+                ci.priority_trait1.clone(),
+                ci.accumulate_trait.value.to_string(),
+                ci.sale_price.clone(),
+                format!("{:.4}", unit_price(ci)),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(20),
+            Constraint::Length(6),
+            Constraint::Length(6),
+            Constraint::Length(10),
+            Constraint::Length(10),
+        ],
+    )
+    .header(
+        Row::new(["挂单", "等级", "经验", "价格", "Price/EXP"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(format!("{} 挂单 (Esc 返回)", name)));
+    frame.render_widget(table, area);
+}
+
+fn format_time(timestamp: i64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp)
+        .single()
+        .map(|time| time.format("%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,354 @@
+use anyhow::{anyhow, bail, Result};
+use cardahoy_api::{
+    market_secondary::MarketSecondaryResponse,
+    nft::{get_nft_card_color, NftCardId},
+};
+use cardahoy_utils::AlertRule;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    sync::Mutex,
+};
+
+// 未设置 window_mins 时的跌幅统计窗口（分钟）
+const DEFAULT_WINDOW_MINS: u64 = 60;
+const RARITIES: [&str; 4] = ["Common", "Rare", "Epic", "Legendary"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    // 底价 Price/EXP 低于
+    PriceBelow(f64),
+    // 底价在窗口内相对最高点的跌幅（百分比）达到
+    FloorDrop { percent: f64, window_mins: u64 },
+    QuantityBelow(u32),
+}
+
+/// A parsed [`AlertRule`]: which cards it watches and what it watches for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    pub card: Option<NftCardId>,
+    pub rarity: Option<&'static str>,
+    pub gold: Option<bool>,
+    pub conditions: Vec<Condition>,
+}
+
+impl Watch {
+    pub fn from_rule(rule: &AlertRule) -> Result<Self> {
+        let card = rule
+            .card
+            .as_deref()
+            .map(|name| find_card(name).ok_or_else(|| anyhow!("未知卡牌: {}", name)))
+            .transpose()?;
+        let rarity = rule
+            .rarity
+            .as_deref()
+            .map(|rarity| {
+                RARITIES
+                    .into_iter()
+                    .find(|r| r.eq_ignore_ascii_case(rarity))
+                    .ok_or_else(|| anyhow!("未知稀有度: {}", rarity))
+            })
+            .transpose()?;
+
+        let mut conditions = Vec::new();
+        if let Some(price) = rule.price_per_exp_below {
+            conditions.push(Condition::PriceBelow(price));
+        }
+        if let Some(percent) = rule.floor_drop_percent {
+            conditions.push(Condition::FloorDrop {
+                percent,
+                window_mins: rule.window_mins.unwrap_or(DEFAULT_WINDOW_MINS),
+            });
+        }
+        if let Some(quantity) = rule.quantity_below {
+            conditions.push(Condition::QuantityBelow(quantity));
+        }
+        if conditions.is_empty() {
+            bail!("价格提醒至少需要一个条件: {:?}", rule);
+        }
+
+        Ok(Self {
+            card,
+            rarity,
+            gold: rule.gold,
+            conditions,
+        })
+    }
+
+    pub fn matches(&self, card_id: u32) -> bool {
+        let Some(card) = NftCardId::from_id(card_id) else {
+            return false;
+        };
+        let color = get_nft_card_color(card);
+        self.card.is_none_or(|c| c == card)
+            && self.rarity.is_none_or(|r| r == color.rarity())
+            && self.gold.is_none_or(|gold| gold == color.is_gold())
+    }
+}
+
+// 按 id、英文名或中文名查找卡牌
+fn find_card(name: &str) -> Option<NftCardId> {
+    let name = name.trim();
+    if let Ok(id) = name.parse::<u32>() {
+        return NftCardId::from_id(id);
+    }
+    NftCardId::to_vec()
+        .into_iter()
+        .find(|card| card.to_string().eq_ignore_ascii_case(name) || card.to_chinese() == name)
+}
+
+/// A condition that started to hold for a card.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub card_id: u32,
+    // 命中的规则序号，对应配置中 [[alerts]] 的顺序
+    pub rule: usize,
+    pub condition: Condition,
+    // 底价、跌幅百分比或在售数量
+    pub observed: f64,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.condition {
+            Condition::PriceBelow(price) => {
+                write!(f, "底价{:.4}低于{}", self.observed, price)
+            }
+            Condition::FloorDrop {
+                percent,
+                window_mins,
+            } => write!(
+                f,
+                "底价{}分钟内下跌{:.2}%, 超过{}%",
+                window_mins, self.observed, percent
+            ),
+            Condition::QuantityBelow(quantity) => {
+                write!(f, "在售数量{}低于{}", self.observed, quantity)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct AlertState {
+    // 每张卡牌最近的底价，(毫秒时间戳, 底价)
+    floors: HashMap<u32, VecDeque<(i64, f64)>>,
+    // 当前满足的 (规则, 条件, 卡牌)，条件恢复前不重复提醒
+    active: HashSet<(usize, usize, u32)>,
+}
+
+/// Evaluates the configured price alerts against secondary market scans.
+///
+/// An alert fires once when its condition starts to hold and again only after
+/// the condition has cleared in a later scan.
+#[derive(Default)]
+pub struct Alerts {
+    watches: Vec<Watch>,
+    state: Mutex<AlertState>,
+}
+
+impl Alerts {
+    pub fn new(rules: &[AlertRule]) -> Result<Self> {
+        let watches = rules
+            .iter()
+            .map(Watch::from_rule)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            watches,
+            state: Mutex::default(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.watches.is_empty()
+    }
+
+    // 底价记录需要保留的时长（毫秒）
+    fn retention(&self) -> i64 {
+        let mins = self
+            .watches
+            .iter()
+            .flat_map(|watch| &watch.conditions)
+            .filter_map(|condition| match condition {
+                Condition::FloorDrop { window_mins, .. } => Some(*window_mins),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        mins as i64 * 60 * 1000
+    }
+
+    /// Records the scan taken at `timestamp` (ms) and returns the alerts that
+    /// started to hold.
+    pub fn evaluate(&self, timestamp: i64, response: &MarketSecondaryResponse) -> Vec<Alert> {
+        if !self.is_enabled() {
+            return vec![];
+        }
+        let retention = self.retention();
+        let mut state = self.state.lock().unwrap();
+        let AlertState { floors, active } = &mut *state;
+        let mut alerts = Vec::new();
+
+        for card in &response.list {
+            // 无在售时底价没有意义
+            let floor = card
+                .floor_price
+                .parse::<f64>()
+                .ok()
+                .filter(|floor| *floor > 0.0 && card.quantity > 0);
+            let samples = floors.entry(card.secondary_id).or_default();
+            if let Some(floor) = floor {
+                samples.push_back((timestamp, floor));
+            }
+            while samples
+                .front()
+                .is_some_and(|(time, _)| *time < timestamp - retention)
+            {
+                samples.pop_front();
+            }
+
+            for (rule, watch) in self.watches.iter().enumerate() {
+                if !watch.matches(card.secondary_id) {
+                    continue;
+                }
+                for (index, condition) in watch.conditions.iter().enumerate() {
+                    let observed = match *condition {
+                        Condition::PriceBelow(price) => floor.filter(|floor| *floor < price),
+                        Condition::FloorDrop {
+                            percent,
+                            window_mins,
+                        } => floor.and_then(|floor| {
+                            let since = timestamp - window_mins as i64 * 60 * 1000;
+                            let peak = samples
+                                .iter()
+                                .filter(|(time, _)| *time >= since)
+                                .map(|(_, floor)| *floor)
+                                .reduce(f64::max)?;
+                            let drop = (peak - floor) / peak * 100.0;
+                            (drop >= percent).then_some(drop)
+                        }),
+                        Condition::QuantityBelow(quantity) => {
+                            (card.quantity < quantity).then_some(card.quantity as f64)
+                        }
+                    };
+
+                    let key = (rule, index, card.secondary_id);
+                    match observed {
+                        Some(observed) => {
+                            if active.insert(key) {
+                                alerts.push(Alert {
+                                    card_id: card.secondary_id,
+                                    rule,
+                                    condition: *condition,
+                                    observed,
+                                });
+                            }
+                        }
+                        None => {
+                            active.remove(&key);
+                        }
+                    }
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secondary(cards: &[(u32, f64, u32)]) -> MarketSecondaryResponse {
+        let list: Vec<serde_json::Value> = cards
+            .iter()
+            .map(|(id, floor, quantity)| {
+                serde_json::json!({
+                    "volume": 0, "quantity": quantity, "secondaryId": id, "secondaryName": "",
+                    "nftName": "Cards", "image": "", "chainNftId": 12,
+                    "floorPrice": floor.to_string(), "priceUnity": "USDT"
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({ "total": list.len(), "list": list })).unwrap()
+    }
+
+    #[test]
+    fn test_watch_selection() {
+        let watch = Watch::from_rule(&AlertRule {
+            card: Some("弹药箱 (金)".into()),
+            quantity_below: Some(3),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(watch.card, Some(NftCardId::AmmoCrateGold));
+        assert!(watch.matches(NftCardId::AmmoCrateGold as u32));
+        assert!(!watch.matches(NftCardId::AmmoCrate as u32));
+
+        let watch = Watch::from_rule(&AlertRule {
+            card: Some("ammo crate".into()),
+            gold: Some(false),
+            price_per_exp_below: Some(0.1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(watch.matches(NftCardId::AmmoCrate as u32));
+
+        assert!(Watch::from_rule(&AlertRule {
+            card: Some("Ammo Crate".into()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(Watch::from_rule(&AlertRule {
+            rarity: Some("Mythic".into()),
+            quantity_below: Some(1),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let ammo = NftCardId::AmmoCrate as u32;
+        let alerts = Alerts::new(&[
+            AlertRule {
+                card: Some(ammo.to_string()),
+                price_per_exp_below: Some(0.05),
+                ..Default::default()
+            },
+            AlertRule {
+                card: Some(ammo.to_string()),
+                floor_drop_percent: Some(20.0),
+                window_mins: Some(30),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let minute = 60 * 1000;
+
+        assert!(alerts.evaluate(0, &secondary(&[(ammo, 0.1, 5)])).is_empty());
+        let fired = alerts.evaluate(10 * minute, &secondary(&[(ammo, 0.04, 5)]));
+        assert_eq!(fired.len(), 2);
+        assert_eq!(fired[0].condition, Condition::PriceBelow(0.05));
+        assert!((fired[1].observed - 60.0).abs() < 1e-9);
+
+        // 条件持续满足时不重复提醒，恢复后再次满足才提醒
+        assert!(alerts
+            .evaluate(11 * minute, &secondary(&[(ammo, 0.04, 5)]))
+            .is_empty());
+        assert!(alerts
+            .evaluate(12 * minute, &secondary(&[(ammo, 0.06, 5)]))
+            .is_empty());
+        assert_eq!(
+            alerts
+                .evaluate(13 * minute, &secondary(&[(ammo, 0.045, 5)]))
+                .len(),
+            1
+        );
+
+        // 0.1 已经超出 30 分钟窗口
+        let mut fired = alerts.evaluate(45 * minute, &secondary(&[(ammo, 0.03, 5)]));
+        fired.retain(|alert| alert.rule == 1);
+        assert!(fired.is_empty());
+    }
+}
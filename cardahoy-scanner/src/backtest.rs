@@ -0,0 +1,403 @@
+use cardahoy_api::market_home::{CardInformation, MarketHomeResponse};
+use comfy_table::Table;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+use crate::history::{Snapshot, SnapshotData};
+use crate::strategy::{self, Strategy, StrategyParams};
+use crate::valuation::FairValueModel;
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    pub strategies: Vec<Strategy>,
+    pub params: StrategyParams,
+    // 便宜挂单被其他人抢先买走的概率
+    pub miss_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            strategies: Strategy::all(),
+            params: StrategyParams::default(),
+            miss_rate: 0.3,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestTrade {
+    pub timestamp: i64,
+    pub strategy: Strategy,
+    pub card_id: u32,
+    pub sale_aggregator_number: String,
+    pub nft_name: String,
+    pub exp: u32,
+    // 买入价格
+    pub price: f64,
+    // 回放结束时按底价估值
+    pub value: f64,
+}
+
+impl BacktestTrade {
+    pub fn pnl(&self) -> f64 {
+        self.value - self.price
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BacktestReport {
+    pub trades: Vec<BacktestTrade>,
+    // 满足条件但被别人抢先的挂单数量
+    pub missed: u32,
+    pub max_drawdown: f64,
+}
+
+impl BacktestReport {
+    pub fn cost(&self) -> f64 {
+        self.trades.iter().map(|t| t.price).sum()
+    }
+
+    pub fn pnl(&self) -> f64 {
+        self.trades.iter().map(|t| t.pnl()).sum()
+    }
+
+    /// Share of trades worth more than their cost at the end of the replay.
+    pub fn hit_rate(&self) -> f64 {
+        if self.trades.is_empty() {
+            return 0.0;
+        }
+        let hits = self.trades.iter().filter(|t| t.pnl() > 0.0).count();
+        hits as f64 / self.trades.len() as f64
+    }
+
+    pub fn fill_rate(&self) -> f64 {
+        let attempts = self.trades.len() as u32 + self.missed;
+        if attempts == 0 {
+            return 0.0;
+        }
+        self.trades.len() as f64 / attempts as f64
+    }
+}
+
+/// Replays recorded snapshots in time order through the buy strategies.
+///
+/// Every home snapshot re-evaluates the enabled strategies for that card
+/// against the latest known market state. Each candidate is filled with
+/// probability `1 - miss_rate`, and open positions are marked to the latest
+/// floor (Price/EXP times EXP) after every snapshot to track drawdown.
+pub struct Backtester<'a> {
+    config: &'a BacktestConfig,
+    // 价格表，底价策略使用
+    prices: &'a HashMap<u32, f64>,
+    homes: HashMap<u32, MarketHomeResponse>,
+    floors: HashMap<u32, f64>,
+    valuation: FairValueModel,
+    seen: HashSet<String>,
+    rng: StdRng,
+    report: BacktestReport,
+}
+
+impl<'a> Backtester<'a> {
+    pub fn new(config: &'a BacktestConfig, prices: &'a HashMap<u32, f64>) -> Self {
+        Self {
+            config,
+            prices,
+            homes: HashMap::new(),
+            floors: HashMap::new(),
+            valuation: FairValueModel::new(),
+            seen: HashSet::new(),
+            rng: StdRng::seed_from_u64(config.seed),
+            report: BacktestReport::default(),
+        }
+    }
+
+    pub fn run(mut self, snapshots: &[Snapshot]) -> BacktestReport {
+        // 历史文件可能由多个进程写入或手工合并，按时间排序后再回放，同一时间保持原顺序
+        let mut ordered: Vec<&Snapshot> = snapshots.iter().collect();
+        ordered.sort_by_key(|snapshot| snapshot.timestamp);
+
+        let mut peak = 0.0_f64;
+        for snapshot in ordered {
+            self.step(snapshot);
+
+            let equity = self.equity();
+            peak = peak.max(equity);
+            self.report.max_drawdown = self.report.max_drawdown.max(peak - equity);
+        }
+
+        for i in 0..self.report.trades.len() {
+            let value = self.mark(&self.report.trades[i]);
+            self.report.trades[i].value = value;
+        }
+        self.report
+    }
+
+    fn step(&mut self, snapshot: &Snapshot) {
+        match &snapshot.data {
+            SnapshotData::Secondary { response } => {
+                for card in &response.list {
+                    if let Ok(floor) = card.floor_price.parse::<f64>() {
+                        self.floors.insert(card.secondary_id, floor);
+                    }
+                }
+            }
+            SnapshotData::Home { card_id, response } => {
+                self.valuation.observe_listings(*card_id, response);
+                self.homes.insert(*card_id, response.clone());
+                for strategy in self.config.strategies.clone() {
+                    for (card_id, ci) in self.candidates(strategy, *card_id) {
+                        self.fill(snapshot.timestamp, strategy, card_id, ci);
+                    }
+                }
+            }
+        }
+    }
+
+    fn candidates(&self, strategy: Strategy, card_id: u32) -> Vec<(u32, CardInformation)> {
+        let params = &self.config.params;
+        let skip = |ci: &CardInformation| self.seen.contains(&ci.sale_aggregator_number);
+        let Some(home) = self.homes.get(&card_id) else {
+            return vec![];
+        };
+
+        let found = match strategy {
+            Strategy::FiveAvg => strategy::five_avg(params, home, skip).map(|(ci, _)| ci),
+            Strategy::NeutralFloor | Strategy::CheapCards => {
+                let floor = self.floors.get(&card_id);
+                let price = self.prices.get(&card_id);
+                let max_exp = (strategy == Strategy::CheapCards).then_some(params.max_exp);
+                match (floor, price) {
+                    (Some(floor), Some(price)) if floor <= price => {
+                        strategy::floor_candidate(*floor, home, max_exp, skip)
+                    }
+                    _ => None,
+                }
+            }
+            Strategy::FairValue => self
+                .valuation
+                .undervalued(card_id, home, params.fair_value_margin, skip)
+                .map(|(ci, _)| ci),
+            Strategy::GoldVersusRegular => {
+                let Some((regular, gold)) = strategy::gold_pair(card_id) else {
+                    return vec![];
+                };
+                let pair = (regular, gold);
+                let (regular, gold) = (regular as u32, gold as u32);
+                return match (self.homes.get(&regular), self.homes.get(&gold)) {
+                    (Some(regular), Some(gold_home)) => {
+                        strategy::gold_versus_regular(params, pair, regular, gold_home)
+                            .1
+                            .into_iter()
+                            .filter(|ci| !skip(ci))
+                            .map(|ci| (gold, ci.clone()))
+                            .collect()
+                    }
+                    _ => vec![],
+                };
+            }
+        };
+        found
+            .map(|ci| vec![(card_id, ci.clone())])
+            .unwrap_or_default()
+    }
+
+    fn fill(&mut self, timestamp: i64, strategy: Strategy, card_id: u32, ci: CardInformation) {
+        if !self.seen.insert(ci.sale_aggregator_number.clone()) {
+            return;
+        }
+        if self.rng.gen::<f64>() < self.config.miss_rate {
+            self.report.missed += 1;
+            return;
+        }
+        let price = ci.sale_price.parse::<f64>().unwrap_or(0.0);
+        self.report.trades.push(BacktestTrade {
+            timestamp,
+            strategy,
+            card_id,
+            sale_aggregator_number: ci.sale_aggregator_number,
+            nft_name: ci.nft_name,
+            exp: ci.accumulate_trait.value,
+            price,
+            value: price,
+        });
+    }
+
+    // 优先使用二级市场底价，没有则使用最新挂单的最低单价
+    fn mark(&self, trade: &BacktestTrade) -> f64 {
+        let unit = self.floors.get(&trade.card_id).copied().or_else(|| {
+            self.homes
+                .get(&trade.card_id)
+                .and_then(|home| home.list.first())
+                .map(strategy::unit_price)
+        });
+        match unit {
+            Some(unit) if unit.is_finite() => unit * trade.exp.max(1) as f64,
+            _ => trade.price,
+        }
+    }
+
+    fn equity(&self) -> f64 {
+        self.report
+            .trades
+            .iter()
+            .map(|trade| self.mark(trade) - trade.price)
+            .sum()
+    }
+}
+
+pub fn run(
+    config: &BacktestConfig,
+    prices: &HashMap<u32, f64>,
+    snapshots: &[Snapshot],
+) -> BacktestReport {
+    Backtester::new(config, prices).run(snapshots)
+}
+
+pub fn print_report(report: &BacktestReport) {
+    let mut table = Table::new();
+    table.set_header(vec!["策略", "成交", "成本", "盈亏", "胜率"]);
+    for strategy in Strategy::all() {
+        let trades: Vec<&BacktestTrade> = report
+            .trades
+            .iter()
+            .filter(|t| t.strategy == strategy)
+            .collect();
+        if trades.is_empty() {
+            continue;
+        }
+        let cost: f64 = trades.iter().map(|t| t.price).sum();
+        let pnl: f64 = trades.iter().map(|t| t.pnl()).sum();
+        let hits = trades.iter().filter(|t| t.pnl() > 0.0).count();
+        table.add_row(vec![
+            strategy.name().to_string(),
+            trades.len().to_string(),
+            format!("{:.4}", cost),
+            format!("{:.4}", pnl),
+            format!("{:.2}%", hits as f64 / trades.len() as f64 * 100.0),
+        ]);
+    }
+    println!("{table}");
+    println!(
+        "成交: {}, 被抢: {}, 成交率: {:.2}%, 成本: {:.4}, 盈亏: {:.4}, 胜率: {:.2}%, 最大回撤: {:.4}",
+        report.trades.len(),
+        report.missed,
+        report.fill_rate() * 100.0,
+        report.cost(),
+        report.pnl(),
+        report.hit_rate() * 100.0,
+        report.max_drawdown
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::tests::home;
+    use cardahoy_api::market_secondary::MarketSecondaryResponse;
+    use serde_json::json;
+
+    fn secondary(timestamp: i64, card_id: u32, floor: f64) -> Snapshot {
+        let response: MarketSecondaryResponse = serde_json::from_value(json!({
+            "total": 1,
+            "list": [{
+                "volume": 0,
+                "quantity": 5,
+                "secondaryId": card_id,
+                "secondaryName": "Axe",
+                "nftName": "Cards",
+                "image": "",
+                "chainNftId": 12,
+                "floorPrice": format!("{}", floor),
+                "priceUnity": "USDT"
+            }]
+        }))
+        .unwrap();
+        Snapshot {
+            timestamp,
+            data: SnapshotData::Secondary { response },
+        }
+    }
+
+    fn home_snapshot(timestamp: i64, card_id: u32, prices: &[f64]) -> Snapshot {
+        Snapshot {
+            timestamp,
+            data: SnapshotData::Home {
+                card_id,
+                response: home(prices),
+            },
+        }
+    }
+
+    #[test]
+    fn test_backtest_five_avg() {
+        let config = BacktestConfig {
+            strategies: vec![Strategy::FiveAvg],
+            miss_rate: 0.0,
+            ..Default::default()
+        };
+        let prices = HashMap::new();
+        let snapshots = vec![
+            home_snapshot(1, 320, &[0.4, 1.0, 1.0, 1.0, 1.0]),
+            // 同一挂单不会重复成交
+            home_snapshot(2, 320, &[0.4, 1.0, 1.0, 1.0, 1.0]),
+            secondary(3, 320, 0.6),
+        ];
+        let report = run(&config, &prices, &snapshots);
+
+        assert_eq!(report.trades.len(), 1);
+        assert!((report.pnl() - 0.2).abs() < 1e-9);
+        assert_eq!(report.hit_rate(), 1.0);
+        assert_eq!(report.max_drawdown, 0.0);
+    }
+
+    #[test]
+    fn test_backtest_misses_and_drawdown() {
+        let config = BacktestConfig {
+            strategies: vec![Strategy::FiveAvg],
+            miss_rate: 1.0,
+            ..Default::default()
+        };
+        let prices = HashMap::new();
+        let snapshots = vec![home_snapshot(1, 320, &[0.4, 1.0, 1.0, 1.0, 1.0])];
+        let report = run(&config, &prices, &snapshots);
+        assert!(report.trades.is_empty());
+        assert_eq!(report.missed, 1);
+
+        let config = BacktestConfig {
+            miss_rate: 0.0,
+            ..config
+        };
+        let snapshots = vec![
+            home_snapshot(1, 320, &[0.4, 1.0, 1.0, 1.0, 1.0]),
+            secondary(2, 320, 0.8),
+            secondary(3, 320, 0.1),
+        ];
+        let report = run(&config, &prices, &snapshots);
+        assert!((report.max_drawdown - 0.7).abs() < 1e-9);
+        assert_eq!(report.hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_backtest_sorts_snapshots() {
+        let config = BacktestConfig {
+            strategies: vec![Strategy::FiveAvg],
+            miss_rate: 0.0,
+            ..Default::default()
+        };
+        let prices = HashMap::new();
+        // 乱序输入按时间回放：先买入，底价先跌到 0.1 再回到 0.8
+        let snapshots = vec![
+            secondary(3, 320, 0.8),
+            home_snapshot(1, 320, &[0.4, 1.0, 1.0, 1.0, 1.0]),
+            secondary(2, 320, 0.1),
+        ];
+        let report = run(&config, &prices, &snapshots);
+        assert_eq!(report.trades.len(), 1);
+        assert_eq!(report.trades[0].timestamp, 1);
+        assert!((report.max_drawdown - 0.3).abs() < 1e-9);
+        assert!((report.pnl() - 0.4).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::{anyhow, bail, Result};
+use cardahoy_api::{
+    self as api,
+    nft::{get_card_by_name, NftCardCategory, NftCardColor},
+    CardsAhoyApi,
+};
+use cardahoy_utils::{Config, ENV_PREFIX};
+use comfy_table::Table;
+use csv::ReaderBuilder;
+use reqwest::header::HeaderValue;
+use std::{collections::HashSet, fmt, io::Read, net::SocketAddr, path::Path};
+
+use crate::alert::Watch;
+use crate::control;
+use crate::daemon::{QuietHours, Schedule};
+use crate::dashboard::{FACTIONS, RARITIES};
+
+pub const FOILS: [&str; 2] = ["Regular", "Gold"];
+// 每个请求都会带上的配置项
+pub const HEADERS: [&str; 4] = ["client_app_id", "authorization", "cookies", "user_agent"];
+
+pub fn parse_faction(faction: &str) -> Result<NftCardCategory> {
+    Ok(match faction {
+        "Neutral" => NftCardCategory::Neutral,
+        "Zombie" => NftCardCategory::Zombie,
+        "Dragon" => NftCardCategory::Dragon,
+        "Mech" => NftCardCategory::Mech,
+        "Animal" => NftCardCategory::Animal,
+        "Plant" => NftCardCategory::Plant,
+        _ => bail!("未知阵营 {:?}, 可选: {}", faction, FACTIONS.join(", ")),
+    })
+}
+
+pub fn parse_color(foil: &str, rarity: &str) -> Result<NftCardColor> {
+    Ok(match (foil, rarity) {
+        ("Regular", "Common") => NftCardColor::White,
+        ("Regular", "Rare") => NftCardColor::Blue,
+        ("Regular", "Epic") => NftCardColor::Purple,
+        ("Regular", "Legendary") => NftCardColor::Orange,
+        ("Gold", "Common") => NftCardColor::WhiteGold,
+        ("Gold", "Rare") => NftCardColor::BlueGold,
+        ("Gold", "Epic") => NftCardColor::PurpleGold,
+        ("Gold", "Legendary") => NftCardColor::OrangeGold,
+        ("Regular" | "Gold", _) => {
+            bail!("未知稀有度 {:?}, 可选: {}", rarity, RARITIES.join(", "))
+        }
+        _ => bail!("未知卡面 {:?}, 可选: {}", foil, FOILS.join(", ")),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Ok => "通过",
+            Severity::Warning => "警告",
+            Severity::Error => "错误",
+        })
+    }
+}
+
+/// The outcome of checking one config item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // 配置项或文件，例如 faction、key.pem
+    pub item: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, item: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            item: item.into(),
+            message: message.into(),
+        }
+    }
+
+    fn from_result(item: impl Into<String>, result: Result<String>) -> Self {
+        match result {
+            Ok(message) => Self::new(Severity::Ok, item, message),
+            Err(e) => Self::new(Severity::Error, item, format!("{:#}", e)),
+        }
+    }
+}
+
+/// Checks everything `Store::new` and the daemon would otherwise fail on at
+/// startup: card filters, request headers, the RSA public key, the
+/// floor-price CSV, listen addresses, the daemon schedule and price alerts.
+pub fn check_config(config: &Config, prices_path: &Path) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![
+        check_filter("faction", &config.faction, |value| {
+            parse_faction(value).map(|_| ())
+        }),
+        check_filter("rarity", &config.rarity, |value| {
+            parse_color("Regular", value).map(|_| ())
+        }),
+        check_filter("foil", &config.foil, |value| {
+            parse_color(value, "Common").map(|_| ())
+        }),
+    ];
+
+    diagnostics.extend(check_headers("", config));
+
+    let source = match (&config.rsa_public_key, &config.rsa_key_path) {
+        (Some(_), _) => "rsa_public_key".to_string(),
+        (None, Some(path)) => path.clone(),
+        (None, None) => "内置公钥".to_string(),
+    };
+    diagnostics.push(Diagnostic::from_result(
+        "rsa_key",
+        api::load_public_key(config).map(|_| format!("{} 有效", source)),
+    ));
+    diagnostics.extend(check_prices(prices_path));
+
+    for (item, addr) in [
+        ("metrics_addr", &config.metrics_addr),
+        ("control_addr", &config.control_addr),
+    ] {
+        if let Some(addr) = addr {
+            diagnostics.push(Diagnostic::from_result(
+                item,
+                addr.parse::<SocketAddr>()
+                    .map(|addr| format!("监听 {}", addr))
+                    .map_err(|e| anyhow!("无效地址 {:?}: {}, 格式如 127.0.0.1:9184", addr, e)),
+            ));
+        }
+    }
+
+    if let Some(Ok(addr)) = config.control_addr.as_ref().map(|addr| addr.parse()) {
+        if let Err(e) = control::check_bind(addr, config.control_token.as_deref()) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                "control_token",
+                e.to_string(),
+            ));
+        }
+    }
+
+    diagnostics.push(check_daemon("daemon", config));
+
+    // 各账户的登录信息与守护进程配置
+    for name in config.profiles.keys() {
+        let prefix = format!("profiles.{}.", name);
+        match config.profile(name) {
+            Ok(profile) => {
+                diagnostics.extend(check_headers(&prefix, &profile));
+                diagnostics.push(check_daemon(&format!("{}daemon", prefix), &profile));
+            }
+            Err(e) => diagnostics.push(Diagnostic::new(Severity::Error, prefix, e.to_string())),
+        }
+    }
+
+    for (index, rule) in config.alerts.iter().enumerate() {
+        diagnostics.push(Diagnostic::from_result(
+            format!("alerts[{}]", index),
+            Watch::from_rule(rule).map(|watch| format!("{}个条件", watch.conditions.len())),
+        ));
+    }
+    diagnostics
+}
+
+fn check_headers(prefix: &str, config: &Config) -> Vec<Diagnostic> {
+    let values = [
+        &config.client_app_id,
+        &config.authorization,
+        &config.cookies,
+        &config.user_agent,
+    ];
+    HEADERS
+        .into_iter()
+        .zip(values)
+        .map(|(item, value)| check_header(&format!("{}{}", prefix, item), value))
+        .collect()
+}
+
+fn check_daemon(item: &str, config: &Config) -> Diagnostic {
+    Diagnostic::from_result(
+        item,
+        Schedule::from_config(&config.daemon).and_then(|_| {
+            for value in &config.daemon.quiet_hours {
+                QuietHours::parse(value)?;
+            }
+            Ok(format!("{}个任务", config.daemon.schedules.len()))
+        }),
+    )
+}
+
+fn check_filter(item: &str, values: &[String], parse: impl Fn(&str) -> Result<()>) -> Diagnostic {
+    if values.is_empty() {
+        return Diagnostic::new(Severity::Ok, item, "不限");
+    }
+    let errors: Vec<String> = values
+        .iter()
+        .filter_map(|value| parse(value).err().map(|e| e.to_string()))
+        .collect();
+    if errors.is_empty() {
+        Diagnostic::new(Severity::Ok, item, values.join(", "))
+    } else {
+        Diagnostic::new(Severity::Error, item, errors.join("; "))
+    }
+}
+
+// 请求头只能包含可见 ASCII 字符，常见问题是复制时带入换行或中文标点
+fn check_header(item: &str, value: &str) -> Diagnostic {
+    if value.is_empty() {
+        return Diagnostic::new(
+            Severity::Warning,
+            item,
+            format!(
+                "为空, 可在文件中填写或设置 {}{}",
+                ENV_PREFIX,
+                item.replace('.', "__").to_uppercase()
+            ),
+        );
+    }
+    if HeaderValue::from_str(value).is_ok() {
+        return Diagnostic::new(Severity::Ok, item, format!("{}个字符", value.len()));
+    }
+    let (position, invalid) = value
+        .chars()
+        .enumerate()
+        .find(|(_, c)| !(*c == '\t' || (' '..='~').contains(c)))
+        .unwrap_or((0, ' '));
+    Diagnostic::new(
+        Severity::Error,
+        item,
+        format!(
+            "不是有效的请求头: 第{}个字符 {:?} 无效, 请去掉换行和非 ASCII 字符",
+            position + 1,
+            invalid
+        ),
+    )
+}
+
+/// Checks that every row of the floor-price CSV names a known card.
+pub fn check_prices(path: &Path) -> Vec<Diagnostic> {
+    let item = path.display().to_string();
+    match ReaderBuilder::new().has_headers(false).from_path(path) {
+        Ok(reader) => check_price_records(&item, reader),
+        Err(e) => vec![Diagnostic::new(Severity::Error, item, e.to_string())],
+    }
+}
+
+fn check_price_records<R: Read>(item: &str, mut reader: csv::Reader<R>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut rows = 0;
+    for (line, record) in reader.records().enumerate() {
+        let location = format!("{}:{}", item, line + 1);
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                diagnostics.push(Diagnostic::new(Severity::Error, location, e.to_string()));
+                continue;
+            }
+        };
+        let name = record.get(0).unwrap_or_default();
+        if get_card_by_name(name).is_none() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                location,
+                format!("未知卡牌 {:?}, 需使用英文卡名", name),
+            ));
+        } else if record.get(1).and_then(|v| v.parse::<f64>().ok()).is_none() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                location,
+                format!(
+                    "{} 的价格无效: {:?}",
+                    name,
+                    record.get(1).unwrap_or_default()
+                ),
+            ));
+        } else {
+            rows += 1;
+        }
+    }
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Ok,
+            item,
+            format!("{}张卡牌的底价", rows),
+        ));
+    }
+    diagnostics
+}
+
+/// Queries the balance to confirm the session headers are accepted.
+pub async fn check_session(api: &CardsAhoyApi) -> Diagnostic {
+    Diagnostic::from_result(
+        "session",
+        api.query_user_balance().await.map(|balances| {
+            let balances: Vec<String> = balances
+                .iter()
+                .map(|b| format!("{} {}", b.balance, b.price_unity))
+                .collect();
+            format!("会话有效, 余额: {}", balances.join(", "))
+        }),
+    )
+}
+
+pub fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    let mut table = Table::new();
+    table.set_header(vec!["结果", "配置项", "说明"]);
+    for diagnostic in diagnostics {
+        table.add_row(vec![
+            diagnostic.severity.to_string(),
+            diagnostic.item.clone(),
+            diagnostic.message.clone(),
+        ]);
+    }
+    println!("{table}");
+}
+
+/// Builds the card filter list for the configured factions, rarities and foils.
+pub fn card_filters(config: &Config) -> Result<(HashSet<NftCardCategory>, HashSet<NftCardColor>)> {
+    let categories = config
+        .faction
+        .iter()
+        .map(|faction| parse_faction(faction))
+        .collect::<Result<_>>()?;
+    let colors = config
+        .foil
+        .iter()
+        .flat_map(|foil| config.rarity.iter().map(|rarity| parse_color(foil, rarity)))
+        .collect::<Result<_>>()?;
+    Ok((categories, colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filters() {
+        assert_eq!(parse_faction("Mech").unwrap(), NftCardCategory::Mech);
+        assert!(parse_faction("mech").is_err());
+        assert_eq!(
+            parse_color("Gold", "Epic").unwrap(),
+            NftCardColor::PurpleGold
+        );
+        assert!(parse_color("Gold", "Mythic")
+            .unwrap_err()
+            .to_string()
+            .contains("稀有度"));
+        assert!(parse_color("Shiny", "Epic")
+            .unwrap_err()
+            .to_string()
+            .contains("卡面"));
+    }
+
+    #[test]
+    fn test_check_headers_and_prices() {
+        assert_eq!(check_header("cookies", "a=1; b=2").severity, Severity::Ok);
+        assert_eq!(
+            check_header("authorization", "").severity,
+            Severity::Warning
+        );
+        let invalid = check_header("authorization", "abc\n");
+        assert_eq!(invalid.severity, Severity::Error);
+        assert!(invalid.message.contains("第4个字符"));
+
+        cardahoy_api::nft::init_nft_card_map();
+        let csv = "Axe,0.005\nNo Such Card,0.1\nAxe (Gold),abc\n";
+        let reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(csv.as_bytes());
+        let diagnostics = check_price_records("neutral.csv", reader);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].item, "neutral.csv:2");
+        assert_eq!(diagnostics[1].item, "neutral.csv:3");
+    }
+}
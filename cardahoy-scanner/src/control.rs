@@ -0,0 +1,260 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use tokio::sync::{watch, Notify};
+
+use crate::daemon::Shutdown;
+use crate::ledger::Trade;
+use crate::strategy::StrategyParams;
+
+/// Summary of the last daemon cycle.
+#[derive(Debug, Clone, Serialize)]
+pub struct CycleStatus {
+    pub cycle: u64,
+    // 开始时间，毫秒时间戳
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub tasks: Vec<String>,
+    pub failed: usize,
+}
+
+/// What the control API reports about the running scanner.
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub paused: bool,
+    // 守护进程配置的任务
+    pub tasks: Vec<String>,
+    pub last_cycle: Option<CycleStatus>,
+    // 最近一次查询到的余额，按货币单位
+    pub balances: BTreeMap<String, f64>,
+    pub params: StrategyParams,
+    // 最近的交易，新的在前
+    pub recent_orders: Vec<Trade>,
+    pub open_listings: usize,
+}
+
+/// A partial change to [`StrategyParams`]; unset fields are left as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamsUpdate {
+    pub top_n: Option<usize>,
+    pub gold_top_n: Option<usize>,
+    pub min_listings: Option<usize>,
+    pub gold_ratio: Option<f64>,
+    // 替换全部按稀有度的金卡倍数，传空表则清除
+    pub gold_rarity_ratio: Option<HashMap<String, f64>>,
+    pub five_avg_ratio: Option<f64>,
+    pub max_exp: Option<u32>,
+    pub fair_value_margin: Option<f64>,
+}
+
+impl ParamsUpdate {
+    pub fn apply(&self, params: &mut StrategyParams) -> Result<()> {
+        if self.top_n == Some(0) || self.gold_top_n == Some(0) {
+            bail!("top_n 与 gold_top_n 必须大于 0");
+        }
+        let rarity_ratios = self
+            .gold_rarity_ratio
+            .iter()
+            .flat_map(|ratios| ratios.values());
+        for ratio in [self.gold_ratio, self.five_avg_ratio]
+            .into_iter()
+            .flatten()
+            .chain(rarity_ratios.copied())
+        {
+            if !(ratio > 0.0 && ratio.is_finite()) {
+                bail!("比例必须为正数: {}", ratio);
+            }
+        }
+        if let Some(margin) = self.fair_value_margin {
+            if !(0.0..1.0).contains(&margin) {
+                bail!("fair_value_margin 必须在 0 到 1 之间: {}", margin);
+            }
+        }
+
+        if let Some(value) = self.top_n {
+            params.top_n = value;
+        }
+        if let Some(value) = self.gold_top_n {
+            params.gold_top_n = value;
+        }
+        if let Some(value) = self.min_listings {
+            params.min_listings = value;
+        }
+        if let Some(value) = self.gold_ratio {
+            params.gold_ratio = value;
+        }
+        if let Some(value) = &self.gold_rarity_ratio {
+            params.gold_rarity_ratio = value.clone();
+        }
+        if let Some(value) = self.five_avg_ratio {
+            params.five_avg_ratio = value;
+        }
+        if let Some(value) = self.max_exp {
+            params.max_exp = value;
+        }
+        if let Some(value) = self.fair_value_margin {
+            params.fair_value_margin = value;
+        }
+        Ok(())
+    }
+}
+
+/// Refuses to expose the control API beyond the loopback interface unless
+/// requests have to carry `token`.
+pub fn check_bind(addr: SocketAddr, token: Option<&str>) -> Result<()> {
+    if !addr.ip().is_loopback() && token.is_none_or(str::is_empty) {
+        bail!("控制接口监听非本机地址 {} 时必须设置 control_token", addr);
+    }
+    Ok(())
+}
+
+/// Whether an `Authorization` header value carries the bearer `token`.
+pub fn authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(given) = header.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    // 逐字节比较全部内容，耗时不随匹配的前缀长度变化
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Runtime switches shared by the daemon and the control API.
+#[derive(Default)]
+pub struct Control {
+    paused: AtomicBool,
+    scan_now: Notify,
+    last_cycle: Mutex<Option<CycleStatus>>,
+    balances: Mutex<BTreeMap<String, f64>>,
+    // 守护进程的退出信号，收到后不再开始新的订单
+    shutdown: Mutex<Option<watch::Receiver<Shutdown>>>,
+}
+
+impl Control {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Pauses or resumes buying. Selling and price alerts keep running.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        tracing::info!("[控制]: {}购买", if paused { "暂停" } else { "恢复" });
+    }
+
+    /// Follows the daemon's shutdown signal, see [`Control::is_stopping`].
+    pub fn watch_shutdown(&self, shutdown: watch::Receiver<Shutdown>) {
+        *self.shutdown.lock().unwrap() = Some(shutdown);
+    }
+
+    /// Whether the daemon has been asked to exit. Strategies still running
+    /// then stop placing new orders.
+    pub fn is_stopping(&self) -> bool {
+        self.shutdown
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|shutdown| *shutdown.borrow() != Shutdown::Running)
+    }
+
+    /// Asks the daemon to start a full cycle now instead of waiting.
+    pub fn request_scan(&self) {
+        // 守护进程正在扫描时保留一次请求，本轮结束后立即再扫描
+        self.scan_now.notify_one();
+    }
+
+    /// Completes when a scan is requested.
+    pub async fn scan_requested(&self) {
+        self.scan_now.notified().await
+    }
+
+    pub fn last_cycle(&self) -> Option<CycleStatus> {
+        self.last_cycle.lock().unwrap().clone()
+    }
+
+    pub fn set_last_cycle(&self, cycle: CycleStatus) {
+        *self.last_cycle.lock().unwrap() = Some(cycle);
+    }
+
+    pub fn balances(&self) -> BTreeMap<String, f64> {
+        self.balances.lock().unwrap().clone()
+    }
+
+    pub fn set_balance(&self, price_unity: &str, balance: f64) {
+        self.balances
+            .lock()
+            .unwrap()
+            .insert(price_unity.to_string(), balance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_update() {
+        let mut params = StrategyParams::default();
+        let update: ParamsUpdate =
+            serde_json::from_str(r#"{"gold_ratio": 1.2, "max_exp": 5}"#).unwrap();
+        update.apply(&mut params).unwrap();
+        assert_eq!(params.gold_ratio, 1.2);
+        assert_eq!(params.max_exp, 5);
+        assert_eq!(params.top_n, StrategyParams::default().top_n);
+
+        let invalid = ParamsUpdate {
+            fair_value_margin: Some(1.5),
+            max_exp: Some(1),
+            ..Default::default()
+        };
+        assert!(invalid.apply(&mut params).is_err());
+        // 校验失败时不修改任何阈值
+        assert_eq!(params.max_exp, 5);
+        assert!(serde_json::from_str::<ParamsUpdate>(r#"{"unknown": 1}"#).is_err());
+
+        let update: ParamsUpdate =
+            serde_json::from_str(r#"{"gold_rarity_ratio": {"Legendary": 1.05}}"#).unwrap();
+        update.apply(&mut params).unwrap();
+        assert_eq!(params.gold_ratio_for("Legendary"), 1.05);
+        assert_eq!(params.gold_ratio_for("Common"), 1.2);
+        let invalid: ParamsUpdate =
+            serde_json::from_str(r#"{"gold_rarity_ratio": {"Epic": 0}}"#).unwrap();
+        assert!(invalid.apply(&mut params).is_err());
+    }
+
+    #[test]
+    fn test_bind_and_token() {
+        let local: SocketAddr = "127.0.0.1:9185".parse().unwrap();
+        let public: SocketAddr = "0.0.0.0:9185".parse().unwrap();
+        assert!(check_bind(local, None).is_ok());
+        assert!(check_bind(public, None).is_err());
+        assert!(check_bind(public, Some("")).is_err());
+        assert!(check_bind(public, Some("secret")).is_ok());
+
+        assert!(authorized(Some("Bearer secret"), "secret"));
+        assert!(!authorized(Some("Bearer secreT"), "secret"));
+        assert!(!authorized(Some("Bearer secret2"), "secret"));
+        assert!(!authorized(Some("secret"), "secret"));
+        assert!(!authorized(None, "secret"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_request() {
+        let control = Control::default();
+        // 先请求后等待也不会丢失
+        control.request_scan();
+        tokio::time::timeout(std::time::Duration::from_secs(1), control.scan_requested())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,476 @@
+use anyhow::{anyhow, Context, Result};
+use cardahoy_utils::DaemonConfig;
+use chrono::{Local, NaiveTime};
+use rand::Rng;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tracing::Instrument;
+
+use crate::control::CycleStatus;
+use crate::store::Store;
+use crate::strategy::Strategy;
+use crate::telemetry;
+
+/// A unit of work the daemon runs on its own schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    Buy(Strategy),
+    Sell,
+    // 只检查价格提醒，不购买
+    Alerts,
+}
+
+impl Task {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sell" => Some(Task::Sell),
+            "alerts" => Some(Task::Alerts),
+            _ => Strategy::from_name(name).map(Task::Buy),
+        }
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Task::Buy(strategy) => write!(f, "{}", strategy.name()),
+            Task::Sell => write!(f, "sell"),
+            Task::Alerts => write!(f, "alerts"),
+        }
+    }
+}
+
+/// A local time window, e.g. "23:30-06:00". Windows may wrap past midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn parse(value: &str) -> Result<Self> {
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| anyhow!("静默时段格式应为 HH:MM-HH:MM: {}", value))?;
+        Ok(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M")?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M")?,
+        })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+struct Job {
+    task: Task,
+    every: Duration,
+    last_run: Option<Instant>,
+}
+
+/// Per-task intervals. A job is due once its interval has passed since its
+/// last run; a zero interval runs it every cycle.
+pub struct Schedule {
+    jobs: Vec<Job>,
+}
+
+impl Schedule {
+    pub fn from_config(config: &DaemonConfig) -> Result<Self> {
+        let mut jobs = config
+            .schedules
+            .iter()
+            .map(|(name, secs)| {
+                let task = Task::from_name(name).ok_or_else(|| anyhow!("未知任务: {}", name))?;
+                Ok(Job {
+                    task,
+                    every: Duration::from_secs(*secs),
+                    last_run: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // 先买后卖，刚买到的卡牌在同一轮即可挂单
+        jobs.sort_by_key(|job| (job.task == Task::Sell, job.task.to_string()));
+        Ok(Self { jobs })
+    }
+
+    pub fn due(&self, now: Instant) -> Vec<Task> {
+        self.jobs
+            .iter()
+            .filter(|job| {
+                job.last_run
+                    .is_none_or(|last| now.duration_since(last) >= job.every)
+            })
+            .map(|job| job.task)
+            .collect()
+    }
+
+    /// Every scheduled task, in run order.
+    pub fn all(&self) -> Vec<Task> {
+        self.jobs.iter().map(|job| job.task).collect()
+    }
+
+    pub fn mark(&mut self, task: Task, now: Instant) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.task == task) {
+            job.last_run = Some(now);
+        }
+    }
+}
+
+/// How far shutdown has progressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    Running,
+    // 完成当前任务后退出
+    Requested,
+    // 在下一个等待点中断当前任务后退出
+    Forced,
+}
+
+/// Returns a receiver that moves to [`Shutdown::Requested`] on SIGINT or
+/// SIGTERM and to [`Shutdown::Forced`] on a second signal.
+pub fn shutdown_signal() -> watch::Receiver<Shutdown> {
+    let (tx, rx) = watch::channel(Shutdown::Running);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        tracing::info!("[守护]: 收到退出信号，完成当前任务后退出，再次按 Ctrl-C 中断当前任务");
+        let _ = tx.send(Shutdown::Requested);
+        wait_for_signal().await;
+        tracing::warn!("[守护]: 再次收到退出信号，中断当前任务");
+        let _ = tx.send(Shutdown::Forced);
+    });
+    rx
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("unable to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+enum Wake {
+    Timeout,
+    Shutdown,
+    // 控制接口要求立即扫描
+    ScanNow,
+}
+
+fn stopping(shutdown: &watch::Receiver<Shutdown>) -> bool {
+    *shutdown.borrow() != Shutdown::Running
+}
+
+// 等待指定时间，期间可被退出信号或立即扫描请求唤醒
+async fn wait(store: &Store, shutdown: &mut watch::Receiver<Shutdown>, duration: Duration) -> Wake {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => Wake::Timeout,
+        Ok(_) = shutdown.wait_for(|state| *state != Shutdown::Running) => Wake::Shutdown,
+        _ = store.control.scan_requested() => Wake::ScanNow,
+    }
+}
+
+// 第二次退出信号
+async fn force_requested(mut shutdown: watch::Receiver<Shutdown>) {
+    if shutdown
+        .wait_for(|state| *state == Shutdown::Forced)
+        .await
+        .is_err()
+    {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Runs the scheduled tasks until shutdown.
+///
+/// After the first signal no new task starts and the running strategy places
+/// no new orders, while an order already past its re-check is bought and
+/// recorded in the ledger before the daemon exits. A second signal drops the running task at its next await point;
+/// its listing stays marked as attempted. Seller and order state is saved
+/// after every cycle and on exit, and reloaded by the next start.
+pub struct Daemon<'a> {
+    store: &'a Store,
+    config: DaemonConfig,
+    schedule: Schedule,
+    quiet_hours: Vec<QuietHours>,
+}
+
+impl<'a> Daemon<'a> {
+    pub fn new(store: &'a Store, config: DaemonConfig) -> Result<Self> {
+        let schedule = Schedule::from_config(&config)?;
+        let quiet_hours = config
+            .quiet_hours
+            .iter()
+            .map(|value| QuietHours::parse(value))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            store,
+            config,
+            schedule,
+            quiet_hours,
+        })
+    }
+
+    fn delay(&self) -> Duration {
+        let jitter = match self.config.jitter_secs {
+            0 => 0,
+            secs => rand::thread_rng().gen_range(0..=secs * 1000),
+        };
+        Duration::from_secs(self.config.interval_secs) + Duration::from_millis(jitter)
+    }
+
+    fn save_state(&self) {
+        if let Err(e) = self.store.save_state() {
+            tracing::error!("[守护]: 保存状态失败, {:?}", e);
+        }
+    }
+
+    pub async fn run(mut self, mut shutdown: watch::Receiver<Shutdown>) -> Result<()> {
+        let started = Instant::now();
+        let mut cycle = 0u64;
+        let mut forced = false;
+        // 策略运行中收到退出信号时，buy_card 不再开始新的订单
+        self.store.control.watch_shutdown(shutdown.clone());
+
+        while !stopping(&shutdown) {
+            let now = Local::now().time();
+            let quiet = self.quiet_hours.iter().find(|quiet| quiet.contains(now));
+            // 手动触发的扫描不受静默时段限制
+            if let (Some(quiet), false) = (quiet, forced) {
+                tracing::info!("[守护]: 静默时段{}，暂停扫描", quiet);
+                match wait(self.store, &mut shutdown, self.delay()).await {
+                    Wake::Shutdown => break,
+                    Wake::ScanNow => forced = true,
+                    Wake::Timeout => {}
+                }
+                continue;
+            }
+
+            cycle += 1;
+            let cycle_started = Instant::now();
+            let started_at = cardahoy_utils::timestamp();
+            let tasks = if forced {
+                tracing::info!("[守护]: 收到扫描请求，运行全部任务");
+                self.schedule.all()
+            } else {
+                self.schedule.due(Instant::now())
+            };
+            forced = false;
+            let paused = self.store.control.is_paused();
+            let (mut ran, mut failed) = (Vec::new(), 0);
+            for task in tasks {
+                // 收到退出信号后不再开始新任务
+                if stopping(&shutdown) {
+                    break;
+                }
+                // 暂停时跳过购买，恢复后立即补上
+                if paused && matches!(task, Task::Buy(_)) {
+                    continue;
+                }
+                ran.push(task.to_string());
+                let run = async {
+                    match task {
+                        Task::Buy(strategy) => self.store.run_strategy(strategy).await,
+                        Task::Sell => self.store.sell_cycle().await,
+                        Task::Alerts => self.store.alert_cycle().await,
+                    }
+                };
+                let result = tokio::select! {
+                    result = run => result,
+                    _ = force_requested(shutdown.clone()) => {
+                        tracing::warn!("[守护]: 已中断{}", task);
+                        break;
+                    }
+                };
+                self.schedule.mark(task, Instant::now());
+                if let Err(e) = result {
+                    failed += 1;
+                    tracing::error!("[守护]: {}失败, {:?}", task, e);
+                    self.store.notify_error(&e);
+                }
+            }
+
+            metrics::histogram!(telemetry::DAEMON_CYCLE_DURATION)
+                .record(cycle_started.elapsed().as_secs_f64());
+            tracing::info!(
+                "[守护]: 心跳 第{}轮, 任务{}个, 失败{}个, 挂单{}个, 已运行{}秒{}",
+                cycle,
+                ran.len(),
+                failed,
+                self.store.seller.listings().len(),
+                started.elapsed().as_secs(),
+                if paused { ", 已暂停购买" } else { "" }
+            );
+            self.store.control.set_last_cycle(CycleStatus {
+                cycle,
+                started_at,
+                duration_ms: cycle_started.elapsed().as_millis() as u64,
+                tasks: ran,
+                failed,
+            });
+            self.save_state();
+
+            match wait(self.store, &mut shutdown, self.delay()).await {
+                Wake::Shutdown => break,
+                Wake::ScanNow => forced = true,
+                Wake::Timeout => {}
+            }
+        }
+
+        self.save_state();
+        tracing::info!(
+            "[守护]: 已退出, 共运行{}轮, 交易记录{}条, 未成交挂单{}个",
+            cycle,
+            self.store.ledger.trades().len(),
+            self.store.seller.listings().len()
+        );
+        Ok(())
+    }
+}
+
+/// Runs one daemon per profile until shutdown. Each account keeps its own
+/// schedule, ledger and request rate while market scans go through the
+/// shared feed.
+pub async fn run_profiles(
+    stores: &[(String, Store)],
+    shutdown: watch::Receiver<Shutdown>,
+) -> Result<()> {
+    let daemons = stores
+        .iter()
+        .map(|(name, store)| {
+            let daemon = Daemon::new(store, store.config.daemon.clone())
+                .with_context(|| format!("账户 {} 的守护进程配置无效", name))?;
+            tracing::info!("[守护]: 启动账户 {}", name);
+            Ok(daemon
+                .run(shutdown.clone())
+                .instrument(tracing::info_span!("profile", name = %name)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    futures::future::try_join_all(daemons).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{
+        tests::{remove_store, test_store},
+        BuyOutcome,
+    };
+    use crate::strategy::tests::listing;
+    use std::collections::HashMap;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let night = QuietHours::parse("23:30-06:00").unwrap();
+        assert!(night.contains(time("23:45")));
+        assert!(night.contains(time("03:00")));
+        assert!(!night.contains(time("06:00")));
+        assert!(!night.contains(time("12:00")));
+
+        let noon = QuietHours::parse("12:00-13:00").unwrap();
+        assert!(noon.contains(time("12:30")));
+        assert!(!noon.contains(time("13:30")));
+
+        assert!(QuietHours::parse("12:00").is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let config = DaemonConfig {
+            schedules: HashMap::from([
+                ("sell".to_string(), 0),
+                ("gold_versus_regular".to_string(), 60),
+            ]),
+            ..Default::default()
+        };
+        let mut schedule = Schedule::from_config(&config).unwrap();
+        let start = Instant::now();
+        let gold = Task::Buy(Strategy::GoldVersusRegular);
+        assert_eq!(schedule.due(start), vec![gold, Task::Sell]);
+
+        schedule.mark(gold, start);
+        schedule.mark(Task::Sell, start);
+        assert_eq!(schedule.due(start), vec![Task::Sell]);
+        assert_eq!(
+            schedule.due(start + Duration::from_secs(60)),
+            vec![gold, Task::Sell]
+        );
+
+        let config = DaemonConfig {
+            schedules: HashMap::from([("unknown".to_string(), 0)]),
+            ..Default::default()
+        };
+        assert!(Schedule::from_config(&config).is_err());
+
+        // 公允价值策略单独扫描，可以与底价扫描同时配置
+        let config = DaemonConfig {
+            schedules: HashMap::from([
+                ("cheap_cards".to_string(), 0),
+                ("fair_value".to_string(), 0),
+            ]),
+            ..Default::default()
+        };
+        let schedule = Schedule::from_config(&config).unwrap();
+        assert_eq!(
+            schedule.all(),
+            vec![
+                Task::Buy(Strategy::CheapCards),
+                Task::Buy(Strategy::FairValue)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_new_orders() {
+        let store = test_store("shutdown", |config| {
+            config.daemon.schedules = HashMap::from([("sell".to_string(), 0)]);
+        });
+        let daemon = Daemon::new(&store, store.config.daemon.clone()).unwrap();
+        let (tx, rx) = watch::channel(Shutdown::Running);
+
+        // 守护进程运行期间仍在执行的策略，收到退出信号后不再下新单
+        let strategy = async {
+            while store.control.last_cycle().is_none() {
+                tokio::task::yield_now().await;
+            }
+            tx.send(Shutdown::Requested).unwrap();
+            let card = listing("SA1", 0.01, 1);
+            let outcome = store.buy_card(Strategy::CheapCards, 320, &card).await;
+            assert_eq!(outcome.unwrap(), BuyOutcome::Stopping);
+            assert!(!store.tracker.contains("SA1"));
+        };
+        let (result, ()) = tokio::join!(daemon.run(rx), strategy);
+        result.unwrap();
+        remove_store(&store);
+    }
+}
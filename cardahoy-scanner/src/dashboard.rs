@@ -0,0 +1,257 @@
+use cardahoy_api::{
+    market_secondary::MarketSecondaryResponse,
+    nft::{get_nft_card_category, get_nft_card_color, NftCardId},
+};
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::strategy::gold_pair;
+
+pub const FACTIONS: [&str; 6] = ["Animal", "Plant", "Zombie", "Neutral", "Dragon", "Mech"];
+pub const RARITIES: [&str; 4] = ["Common", "Rare", "Epic", "Legendary"];
+
+/// One card in the market overview.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardRow {
+    pub card_id: u32,
+    pub name: String,
+    pub faction: String,
+    pub rarity: &'static str,
+    pub gold: bool,
+    // 底价，即市场概览返回的 floorPrice 原值，与策略中比较的底价相同，无在售时为空
+    pub floor: Option<f64>,
+    pub quantity: u32,
+    pub volume: u32,
+    // 相对首次刷新时底价的变化比例
+    pub change: Option<f64>,
+    // 金卡底价 / 普卡底价，普卡与金卡两行相同
+    pub gold_spread: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Floor,
+    Quantity,
+    Volume,
+    Change,
+    Spread,
+}
+
+impl SortKey {
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Floor,
+            SortKey::Floor => SortKey::Quantity,
+            SortKey::Quantity => SortKey::Volume,
+            SortKey::Volume => SortKey::Change,
+            SortKey::Change => SortKey::Spread,
+            SortKey::Spread => SortKey::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "名称",
+            SortKey::Floor => "底价",
+            SortKey::Quantity => "在售",
+            SortKey::Volume => "成交",
+            SortKey::Change => "涨跌",
+            SortKey::Spread => "金/普",
+        }
+    }
+}
+
+// 在 None 与各个选项之间循环
+fn cycle(current: Option<&'static str>, options: &[&'static str]) -> Option<&'static str> {
+    match current.and_then(|c| options.iter().position(|o| *o == c)) {
+        None => options.first().copied(),
+        Some(index) => options.get(index + 1).copied(),
+    }
+}
+
+// 空值始终排在最后
+fn compare_option(a: Option<f64>, b: Option<f64>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.total_cmp(&a),
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Market overview state behind the TUI: the latest secondary listing per
+/// card, with sorting and faction/rarity filters.
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    pub rows: Vec<CardRow>,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub faction: Option<&'static str>,
+    pub rarity: Option<&'static str>,
+    // 最近一次刷新的毫秒时间戳
+    pub updated: Option<i64>,
+    baseline: HashMap<u32, f64>,
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self {
+            rows: vec![],
+            sort: SortKey::Volume,
+            descending: true,
+            faction: None,
+            rarity: None,
+            updated: None,
+            baseline: HashMap::new(),
+        }
+    }
+}
+
+impl Dashboard {
+    pub fn update(&mut self, timestamp: i64, response: &MarketSecondaryResponse) {
+        let floors: HashMap<u32, f64> = response
+            .list
+            .iter()
+            .filter(|card| card.quantity > 0)
+            .filter_map(|card| {
+                let floor = card.floor_price.parse::<f64>().ok()?;
+                (floor > 0.0).then_some((card.secondary_id, floor))
+            })
+            .collect();
+        for (card_id, floor) in &floors {
+            self.baseline.entry(*card_id).or_insert(*floor);
+        }
+
+        self.rows = response
+            .list
+            .iter()
+            .filter_map(|card| {
+                let id = NftCardId::from_id(card.secondary_id)?;
+                let color = get_nft_card_color(id);
+                let floor = floors.get(&card.secondary_id).copied();
+                let gold_spread = gold_pair(card.secondary_id).and_then(|(regular, gold)| {
+                    Some(floors.get(&(gold as u32))? / floors.get(&(regular as u32))?)
+                });
+                Some(CardRow {
+                    card_id: card.secondary_id,
+                    name: id.to_chinese().to_string(),
+                    faction: format!("{:?}", get_nft_card_category(id)),
+                    rarity: color.rarity(),
+                    gold: color.is_gold(),
+                    floor,
+                    quantity: card.quantity,
+                    volume: card.volume,
+                    change: floor.map(|floor| {
+                        let base = self.baseline[&card.secondary_id];
+                        (floor - base) / base
+                    }),
+                    gold_spread,
+                })
+            })
+            .collect();
+        self.updated = Some(timestamp);
+    }
+
+    pub fn cycle_faction(&mut self) {
+        self.faction = cycle(self.faction, &FACTIONS);
+    }
+
+    pub fn cycle_rarity(&mut self) {
+        self.rarity = cycle(self.rarity, &RARITIES);
+    }
+
+    /// Rows passing the filters, in display order.
+    pub fn visible(&self) -> Vec<&CardRow> {
+        let mut rows: Vec<&CardRow> = self
+            .rows
+            .iter()
+            .filter(|row| self.faction.is_none_or(|f| row.faction == f))
+            .filter(|row| self.rarity.is_none_or(|r| row.rarity == r))
+            .collect();
+        let descending = self.descending;
+        rows.sort_by(|a, b| {
+            let ordering = match self.sort {
+                SortKey::Name => a.name.cmp(&b.name),
+                SortKey::Floor => return compare_option(a.floor, b.floor, descending),
+                SortKey::Quantity => a.quantity.cmp(&b.quantity),
+                SortKey::Volume => a.volume.cmp(&b.volume),
+                SortKey::Change => return compare_option(a.change, b.change, descending),
+                SortKey::Spread => return compare_option(a.gold_spread, b.gold_spread, descending),
+            };
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secondary(cards: &[(NftCardId, f64, u32, u32)]) -> MarketSecondaryResponse {
+        let list: Vec<serde_json::Value> = cards
+            .iter()
+            .map(|(id, floor, quantity, volume)| {
+                serde_json::json!({
+                    "volume": volume, "quantity": quantity, "secondaryId": *id as u32,
+                    "secondaryName": "", "nftName": "Cards", "image": "", "chainNftId": 12,
+                    "floorPrice": floor.to_string(), "priceUnity": "USDT"
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({ "total": list.len(), "list": list })).unwrap()
+    }
+
+    #[test]
+    fn test_dashboard() {
+        let mut dashboard = Dashboard::default();
+        dashboard.update(
+            1,
+            &secondary(&[
+                (NftCardId::AmmoCrate, 0.1, 20, 5),
+                (NftCardId::AmmoCrateGold, 0.15, 3, 1),
+                (NftCardId::WolfKing, 2.0, 0, 9),
+            ]),
+        );
+        dashboard.update(
+            2,
+            &secondary(&[
+                (NftCardId::AmmoCrate, 0.12, 18, 7),
+                (NftCardId::AmmoCrateGold, 0.15, 3, 1),
+                (NftCardId::WolfKing, 2.0, 0, 9),
+            ]),
+        );
+
+        let rows = dashboard.visible();
+        assert_eq!(rows[0].card_id, NftCardId::WolfKing as u32);
+        // 无在售时没有底价
+        assert_eq!(rows[0].floor, None);
+
+        dashboard.sort = SortKey::Change;
+        let ammo = dashboard.visible()[0];
+        assert_eq!(ammo.card_id, NftCardId::AmmoCrate as u32);
+        assert!((ammo.change.unwrap() - 0.2).abs() < 1e-9);
+        assert!((ammo.gold_spread.unwrap() - 1.25).abs() < 1e-9);
+
+        dashboard.cycle_faction();
+        dashboard.cycle_faction();
+        dashboard.cycle_faction();
+        dashboard.cycle_faction();
+        assert_eq!(dashboard.faction, Some("Neutral"));
+        dashboard.cycle_rarity();
+        assert!(dashboard.visible().is_empty());
+        dashboard.cycle_rarity();
+        dashboard.cycle_rarity();
+        assert_eq!(dashboard.rarity, Some("Epic"));
+        dashboard.sort = SortKey::Floor;
+        dashboard.descending = false;
+        let rows = dashboard.visible();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].card_id, NftCardId::AmmoCrate as u32);
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use cardahoy_api::{
+    self as api,
+    market_home::{MarketHomeResponse, MarketHomeSortType},
+    market_secondary::MarketSecondaryResponse,
+    nft::{NftId, NftSortType},
+    CardsAhoyApi,
+};
+use cardahoy_utils::{self as utils, Config};
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+use crate::alert::{Alert, Alerts};
+use crate::history::HistoryStore;
+
+/// A secondary market scan and the price alerts it triggered. Alerts are
+/// only evaluated when the scan was fetched rather than served from the
+/// cache, so each one is reported by a single account.
+pub struct Scan {
+    pub resp: MarketSecondaryResponse,
+    pub alerts: Vec<Alert>,
+}
+
+type Slot<V> = Arc<Mutex<Option<(Instant, V)>>>;
+
+// 按查询参数分别加锁：不同查询可以并发，同时发起的相同查询只请求一次
+struct Cache<K, V> {
+    max_age: Duration,
+    slots: std::sync::Mutex<HashMap<K, Slot<V>>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Cache<K, V> {
+    fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            slots: std::sync::Mutex::default(),
+        }
+    }
+
+    fn slot(&self, key: &K) -> Slot<V> {
+        let mut slots = self.slots.lock().unwrap();
+        // 没有请求在等待且已过期的条目直接丢弃
+        slots.retain(|_, slot| {
+            Arc::strong_count(slot) > 1
+                || slot.try_lock().is_ok_and(|entry| {
+                    entry
+                        .as_ref()
+                        .is_some_and(|(fetched, _)| fetched.elapsed() < self.max_age)
+                })
+        });
+        slots.entry(key.clone()).or_default().clone()
+    }
+
+    /// The cached value, or the result of `fetch`; the flag is true when the
+    /// value was fetched.
+    async fn get_or_fetch<F, Fut>(&self, key: &K, fetch: F) -> Result<(V, bool)>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let slot = self.slot(key);
+        // 持有该查询的锁直到请求完成
+        let mut entry = slot.lock().await;
+        if let Some((fetched, value)) = entry.as_ref() {
+            if fetched.elapsed() < self.max_age {
+                return Ok((value.clone(), false));
+            }
+        }
+        let value = fetch().await?;
+        *entry = (!self.max_age.is_zero()).then(|| (Instant::now(), value.clone()));
+        Ok((value, true))
+    }
+}
+
+/// Market data shared by every account, along with the snapshot history and
+/// price alerts that are kept once for the whole market.
+///
+/// Queries with the same parameters within `market_cache_secs` are answered
+/// from the cache, so running several profiles scans the market once;
+/// concurrent identical queries wait for the first one, while different
+/// queries run side by side.
+///
+/// The feed should get its own `api` client rather than an account's trading
+/// client: market scans then wait on their own `request_interval` and do not
+/// delay orders. It still sends the credentials of the profile it was built
+/// from.
+pub struct MarketFeed {
+    api: Arc<CardsAhoyApi>,
+    history: Option<HistoryStore>,
+    alerts: Alerts,
+    secondary: Cache<String, MarketSecondaryResponse>,
+    home: Cache<u32, MarketHomeResponse>,
+}
+
+impl MarketFeed {
+    pub fn new(api: Arc<CardsAhoyApi>, config: &Config) -> Result<Self> {
+        let max_age = Duration::from_secs(config.market_cache_secs.unwrap_or(0));
+        Ok(Self {
+            api,
+            history: config.history_path.as_ref().map(HistoryStore::new),
+            alerts: Alerts::new(&config.alerts)?,
+            secondary: Cache::new(max_age),
+            home: Cache::new(max_age),
+        })
+    }
+
+    pub async fn secondary(
+        &self,
+        page: u32,
+        page_size: u32,
+        sort_type: NftSortType,
+        discrete_list: &Vec<api::filter::Discrete>,
+    ) -> Result<Scan> {
+        let key = format!(
+            "{}:{}:{:?}:{}",
+            page,
+            page_size,
+            sort_type,
+            serde_json::to_string(discrete_list)?
+        );
+        let (resp, fetched) = self
+            .secondary
+            .get_or_fetch(&key, || async {
+                let resp = self
+                    .api
+                    .query_market_secondary(NftId::Cards, page, page_size, sort_type, discrete_list)
+                    .await?;
+                self.record_history(|history| history.record_secondary(&resp));
+                Ok(resp)
+            })
+            .await?;
+        let alerts = if fetched {
+            self.alerts.evaluate(utils::timestamp(), &resp)
+        } else {
+            vec![]
+        };
+        Ok(Scan { resp, alerts })
+    }
+
+    pub async fn home(&self, card_id: u32) -> Result<MarketHomeResponse> {
+        let (resp, _) = self
+            .home
+            .get_or_fetch(&card_id, || async {
+                let resp = self
+                    .api
+                    .query_market_home(
+                        NftId::Cards,
+                        card_id,
+                        1,
+                        MarketHomeSortType::PriceExpAscending,
+                    )
+                    .await?;
+                self.record_history(|history| history.record_home(card_id, &resp));
+                Ok(resp)
+            })
+            .await?;
+        Ok(resp)
+    }
+
+    // 记录行情快照用于回测，写入失败不影响扫描；缓存命中不重复记录
+    fn record_history(&self, record: impl FnOnce(&HistoryStore) -> Result<()>) {
+        if let Some(history) = &self.history {
+            if let Err(e) = record(history) {
+                tracing::error!("[历史]: 记录行情失败, {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_cache_per_key() {
+        let cache: Arc<Cache<u32, u32>> = Arc::new(Cache::new(Duration::from_secs(60)));
+        let fetches = Arc::new(AtomicU32::new(0));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+
+        // 第一个查询挂起期间，其他参数的查询不受影响
+        let slow = tokio::spawn({
+            let (cache, fetches) = (cache.clone(), fetches.clone());
+            async move {
+                cache
+                    .get_or_fetch(&1, || async {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        started_tx.send(()).unwrap();
+                        release_rx.await.unwrap();
+                        Ok(10)
+                    })
+                    .await
+                    .unwrap()
+            }
+        });
+        started_rx.await.unwrap();
+        let other = cache.get_or_fetch(&2, || async { Ok(20) }).await.unwrap();
+        assert_eq!(other, (20, true));
+
+        // 相同查询等待第一个完成后直接使用缓存
+        let waiting = tokio::spawn({
+            let cache = cache.clone();
+            async move { cache.get_or_fetch(&1, || async { Ok(11) }).await.unwrap() }
+        });
+        release_tx.send(()).unwrap();
+        assert_eq!(slow.await.unwrap(), (10, true));
+        assert_eq!(waiting.await.unwrap(), (10, false));
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use cardahoy_api::{market_home::MarketHomeResponse, market_secondary::MarketSecondaryResponse};
+use cardahoy_utils as utils;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A recorded API response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SnapshotData {
+    // queryMarketHome，单张卡牌的挂单列表
+    Home {
+        card_id: u32,
+        response: MarketHomeResponse,
+    },
+    // queryMarketSecondary，卡牌底价、在售数量、成交数量
+    Secondary {
+        response: MarketSecondaryResponse,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    // 毫秒时间戳
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub data: SnapshotData,
+}
+
+/// Append-only JSON Lines file of market responses, used for backtesting.
+pub struct HistoryStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl HistoryStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(&self, data: SnapshotData) -> Result<()> {
+        let snapshot = Snapshot {
+            timestamp: utils::timestamp(),
+            data,
+        };
+        let line = serde_json::to_string(&snapshot)?;
+
+        let _guard = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    pub fn record_home(&self, card_id: u32, response: &MarketHomeResponse) -> Result<()> {
+        self.record(SnapshotData::Home {
+            card_id,
+            response: response.clone(),
+        })
+    }
+
+    pub fn record_secondary(&self, response: &MarketSecondaryResponse) -> Result<()> {
+        self.record(SnapshotData::Secondary {
+            response: response.clone(),
+        })
+    }
+
+    /// Loads every snapshot, sorted by timestamp.
+    pub fn load(&self) -> Result<Vec<Snapshot>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut snapshots = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            snapshots.push(serde_json::from_str::<Snapshot>(&line)?);
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.timestamp);
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::tests::home;
+
+    #[test]
+    fn test_record_and_load() {
+        let path = std::env::temp_dir().join(format!("history-{}.jsonl", utils::timestamp()));
+        let history = HistoryStore::new(&path);
+        history.record_home(320, &home(&[0.4, 1.0])).unwrap();
+        history.record_home(437, &home(&[2.0])).unwrap();
+
+        let snapshots = history.load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(snapshots.len(), 2);
+        match &snapshots[0].data {
+            SnapshotData::Home { card_id, response } => {
+                assert_eq!(*card_id, 320);
+                assert_eq!(response.list.len(), 2);
+            }
+            _ => panic!("expected a home snapshot"),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// One buy or sell, as recorded in the ledger file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    pub side: TradeSide,
+    // nft::NftCardId 具体的数字值
+    pub card_id: u32,
+    pub token_id: String,
+    pub nft_name: String,
+    // 卡牌经验，用于按 Price/EXP 的底价估值
+    pub exp: u32,
+    pub price: f64,
+    pub fee: f64,
+    // 毫秒时间戳
+    pub timestamp: i64,
+    // 触发此交易的策略，卖出时沿用买入的策略
+    pub strategy: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StrategyPnl {
+    pub strategy: String,
+    pub buys: u32,
+    pub sells: u32,
+    // 买入总成本（含手续费）
+    pub cost: f64,
+    pub realized: f64,
+    pub unrealized: f64,
+}
+
+impl StrategyPnl {
+    /// Total return over the capital spent by the strategy.
+    pub fn return_rate(&self) -> f64 {
+        if self.cost == 0.0 {
+            0.0
+        } else {
+            (self.realized + self.unrealized) / self.cost
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PnlReport {
+    pub realized: f64,
+    pub unrealized: f64,
+    pub strategies: Vec<StrategyPnl>,
+}
+
+/// Append-only record of every buy and sell, persisted as CSV.
+pub struct Ledger {
+    path: Option<PathBuf>,
+    trades: Mutex<Vec<Trade>>,
+}
+
+impl Ledger {
+    /// Opens the ledger at `path`, loading existing trades if the file exists.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut trades = Vec::new();
+        if path.exists() {
+            let mut rdr = ReaderBuilder::new().from_path(&path)?;
+            for result in rdr.deserialize() {
+                trades.push(result?);
+            }
+        }
+        Ok(Self {
+            path: Some(path),
+            trades: Mutex::new(trades),
+        })
+    }
+
+    /// A ledger that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            trades: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, trade: Trade) -> Result<()> {
+        if let Some(path) = &self.path {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let has_headers = file.metadata()?.len() == 0;
+            let mut writer = WriterBuilder::new()
+                .has_headers(has_headers)
+                .from_writer(file);
+            writer.serialize(&trade)?;
+            writer.flush()?;
+        }
+        self.trades.lock().unwrap().push(trade);
+        Ok(())
+    }
+
+    pub fn trades(&self) -> Vec<Trade> {
+        self.trades.lock().unwrap().clone()
+    }
+
+    /// Total paid for cards bought at or after `timestamp` (ms).
+    pub fn spent_since(&self, timestamp: i64) -> f64 {
+        self.trades
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|t| t.side == TradeSide::Buy && t.timestamp >= timestamp)
+            .map(|t| t.price)
+            .sum()
+    }
+
+    /// Cards bought and not yet sold.
+    pub fn open_positions(&self) -> Vec<Trade> {
+        open_positions(&self.trades.lock().unwrap())
+    }
+
+    /// Computes realized P&L from matched buys and sells and unrealized P&L
+    /// from open positions marked to `floors` (Price/EXP by card id).
+    pub fn report(&self, floors: &HashMap<u32, f64>) -> PnlReport {
+        report(&self.trades.lock().unwrap(), floors)
+    }
+}
+
+fn open_positions(trades: &[Trade]) -> Vec<Trade> {
+    let mut open: HashMap<&str, &Trade> = HashMap::new();
+    for trade in trades {
+        match trade.side {
+            TradeSide::Buy => {
+                open.insert(&trade.token_id, trade);
+            }
+            TradeSide::Sell => {
+                open.remove(trade.token_id.as_str());
+            }
+        }
+    }
+    let mut positions: Vec<Trade> = open.into_values().cloned().collect();
+    positions.sort_by_key(|trade| trade.timestamp);
+    positions
+}
+
+pub fn report(trades: &[Trade], floors: &HashMap<u32, f64>) -> PnlReport {
+    let mut strategies: HashMap<String, StrategyPnl> = HashMap::new();
+    let mut open: HashMap<&str, &Trade> = HashMap::new();
+
+    for trade in trades {
+        match trade.side {
+            TradeSide::Buy => {
+                let pnl = strategies
+                    .entry(trade.strategy.clone())
+                    .or_insert_with(|| StrategyPnl {
+                        strategy: trade.strategy.clone(),
+                        ..Default::default()
+                    });
+                pnl.buys += 1;
+                pnl.cost += trade.price + trade.fee;
+                open.insert(&trade.token_id, trade);
+            }
+            TradeSide::Sell => {
+                // 卖出与同一 token_id 最近一次买入配对
+                let Some(buy) = open.remove(trade.token_id.as_str()) else {
+                    continue;
+                };
+                let pnl = strategies
+                    .entry(buy.strategy.clone())
+                    .or_insert_with(|| StrategyPnl {
+                        strategy: buy.strategy.clone(),
+                        ..Default::default()
+                    });
+                pnl.sells += 1;
+                pnl.realized += (trade.price - trade.fee) - (buy.price + buy.fee);
+            }
+        }
+    }
+
+    for buy in open.values() {
+        if let Some(floor) = floors.get(&buy.card_id) {
+            let value = floor * buy.exp.max(1) as f64;
+            if let Some(pnl) = strategies.get_mut(&buy.strategy) {
+                pnl.unrealized += value - (buy.price + buy.fee);
+            }
+        }
+    }
+
+    let mut strategies: Vec<StrategyPnl> = strategies.into_values().collect();
+    strategies.sort_by(|a, b| a.strategy.cmp(&b.strategy));
+
+    PnlReport {
+        realized: strategies.iter().map(|s| s.realized).sum(),
+        unrealized: strategies.iter().map(|s| s.unrealized).sum(),
+        strategies,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(side: TradeSide, token_id: &str, price: f64, fee: f64, strategy: &str) -> Trade {
+        Trade {
+            side,
+            card_id: 320,
+            token_id: token_id.into(),
+            nft_name: format!("Ammo Crate #{}", token_id),
+            exp: 2,
+            price,
+            fee,
+            timestamp: 0,
+            strategy: strategy.into(),
+        }
+    }
+
+    #[test]
+    fn test_report_realized_and_unrealized() {
+        let trades = vec![
+            trade(TradeSide::Buy, "1", 1.0, 0.0, "five_avg"),
+            trade(TradeSide::Buy, "2", 2.0, 0.0, "cheap_cards"),
+            trade(TradeSide::Sell, "1", 1.5, 0.1, "five_avg"),
+        ];
+        let floors = HashMap::from([(320, 1.25)]);
+        let report = report(&trades, &floors);
+
+        assert!((report.realized - 0.4).abs() < 1e-9);
+        assert!((report.unrealized - 0.5).abs() < 1e-9);
+
+        let five_avg = &report.strategies[1];
+        assert_eq!(five_avg.strategy, "five_avg");
+        assert_eq!((five_avg.buys, five_avg.sells), (1, 1));
+        assert!((five_avg.return_rate() - 0.4).abs() < 1e-9);
+
+        let cheap = &report.strategies[0];
+        assert!((cheap.return_rate() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_open_positions() {
+        let ledger = Ledger::in_memory();
+        ledger
+            .record(trade(TradeSide::Buy, "1", 1.0, 0.0, "five_avg"))
+            .unwrap();
+        ledger
+            .record(trade(TradeSide::Buy, "2", 1.0, 0.0, "five_avg"))
+            .unwrap();
+        ledger
+            .record(trade(TradeSide::Sell, "1", 1.0, 0.0, "five_avg"))
+            .unwrap();
+        let open = ledger.open_positions();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].token_id, "2");
+
+        ledger
+            .record(Trade {
+                timestamp: 100,
+                ..trade(TradeSide::Buy, "3", 0.5, 0.0, "five_avg")
+            })
+            .unwrap();
+        assert_eq!(ledger.spent_since(0), 2.5);
+        assert_eq!(ledger.spent_since(100), 0.5);
+    }
+}
@@ -1,2 +1,20 @@
+pub mod alert;
+pub mod backtest;
+pub mod check;
+pub mod control;
+pub mod daemon;
+pub mod dashboard;
+pub mod feed;
+pub mod history;
+pub mod ledger;
+pub mod notify;
 pub mod script;
+pub mod seller;
+pub mod snapshot;
+pub mod stats;
 pub mod store;
+pub mod strategy;
+pub mod telemetry;
+pub mod tracker;
+pub mod valuation;
+pub mod verify;
@@ -0,0 +1,400 @@
+use anyhow::{bail, Result};
+use cardahoy_utils::{self as utils, NotifyConfig, NotifySink};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::Write,
+    process::Stdio,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+// 待发送通知的队列长度，队列满时丢弃新事件
+const QUEUE_SIZE: usize = 256;
+
+/// Something worth telling a human about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PurchaseSucceeded {
+        card_id: u32,
+        nft_name: String,
+        price: f64,
+        strategy: String,
+    },
+    PurchaseFailed {
+        card_id: u32,
+        nft_name: String,
+        price: f64,
+        strategy: String,
+        reason: String,
+    },
+    ListingSold {
+        card_id: u32,
+        nft_name: String,
+        price: f64,
+    },
+    // 余额不足以购买挂单
+    BudgetExhausted {
+        balance: f64,
+        price: f64,
+    },
+    // 当天买入金额达到 daily_budget，与余额不足同属 budget_exhausted
+    #[serde(rename = "budget_exhausted")]
+    DailyBudgetReached {
+        spent: f64,
+        budget: f64,
+        price: f64,
+    },
+    // 登录过期，需要更新 config.toml 中的 authorization 与 cookies
+    SessionExpired {
+        reason: String,
+    },
+    // 价格提醒规则命中
+    PriceAlert {
+        card_id: u32,
+        nft_name: String,
+        condition: String,
+    },
+}
+
+impl Event {
+    /// The name used in the `events` filter of a sink.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::PurchaseSucceeded { .. } => "purchase_succeeded",
+            Event::PurchaseFailed { .. } => "purchase_failed",
+            Event::ListingSold { .. } => "listing_sold",
+            Event::BudgetExhausted { .. } | Event::DailyBudgetReached { .. } => "budget_exhausted",
+            Event::SessionExpired { .. } => "session_expired",
+            Event::PriceAlert { .. } => "price_alert",
+        }
+    }
+
+    // 限流的分组，价格提醒按卡牌分别限流
+    fn rate_key(&self) -> String {
+        match self {
+            Event::PriceAlert { card_id, .. } => format!("{}:{}", self.kind(), card_id),
+            _ => self.kind().to_string(),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Event::PurchaseSucceeded {
+                nft_name, price, ..
+            } => format!("购买{}成功, 价格为{}", nft_name, price),
+            Event::PurchaseFailed {
+                nft_name, reason, ..
+            } => format!("购买{}失败, {}", nft_name, reason),
+            Event::ListingSold {
+                nft_name, price, ..
+            } => format!("{}已成交, 价格为{}", nft_name, price),
+            Event::BudgetExhausted { balance, price } => {
+                format!("余额不足, 余额{}, 需要{}", balance, price)
+            }
+            Event::DailyBudgetReached {
+                spent,
+                budget,
+                price,
+            } => format!(
+                "已达每日预算, 今日已买入{}, 预算{}, 需要{}",
+                spent, budget, price
+            ),
+            Event::SessionExpired { reason } => format!("登录已过期, {}", reason),
+            Event::PriceAlert {
+                nft_name,
+                condition,
+                ..
+            } => format!("价格提醒: {}, {}", nft_name, condition),
+        }
+    }
+}
+
+/// The JSON document delivered to every sink.
+#[derive(Debug, Serialize)]
+pub struct Notification<'a> {
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: &'a Event,
+    pub message: String,
+    // 上次通知后因限流未发送的同类事件数量
+    pub suppressed: u32,
+}
+
+struct RateState {
+    last: Instant,
+    suppressed: u32,
+}
+
+/// Delivers events to the configured sinks.
+///
+/// Each sink gets at most one notification per event kind (per card for
+/// price alerts) every `rate_limit_secs`; the rest are counted and reported
+/// with the next one.
+/// [`Notifier::notify`] only queues the event; a background task delivers
+/// the queue in order, so slow webhooks or commands never hold up an order.
+/// Delivery failures are logged and never interrupt trading.
+pub struct Notifier {
+    dispatcher: Arc<Dispatcher>,
+    queue: OnceLock<mpsc::Sender<Event>>,
+}
+
+struct Dispatcher {
+    sinks: Vec<NotifySink>,
+    rate_limit: Duration,
+    client: reqwest::Client,
+    sent: Mutex<HashMap<(usize, String), RateState>>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotifyConfig) -> Self {
+        Self {
+            dispatcher: Arc::new(Dispatcher {
+                sinks: config.sinks.clone(),
+                rate_limit: Duration::from_secs(config.rate_limit_secs),
+                client: reqwest::Client::new(),
+                sent: Mutex::new(HashMap::new()),
+            }),
+            queue: OnceLock::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.dispatcher.sinks.is_empty()
+    }
+
+    /// Queues the event for delivery without waiting for the sinks.
+    ///
+    /// Must be called from within a Tokio runtime; the delivery task is
+    /// started on first use.
+    pub fn notify(&self, event: Event) {
+        if !self.is_enabled() {
+            return;
+        }
+        let queue = self.queue.get_or_init(|| {
+            let (tx, mut rx) = mpsc::channel::<Event>(QUEUE_SIZE);
+            let dispatcher = self.dispatcher.clone();
+            tokio::spawn(async move {
+                while let Some(event) = rx.recv().await {
+                    dispatcher.deliver(event).await;
+                }
+            });
+            tx
+        });
+        if let Err(mpsc::error::TrySendError::Full(event)) = queue.try_send(event) {
+            tracing::error!("[通知]: 队列已满, 丢弃{}", event.kind());
+        }
+    }
+
+    /// Delivers the event right away, waiting for every sink.
+    pub async fn deliver(&self, event: Event) {
+        self.dispatcher.deliver(event).await;
+    }
+}
+
+impl Dispatcher {
+    async fn deliver(&self, event: Event) {
+        for (index, sink) in self.sinks.iter().enumerate() {
+            let kind = event.kind();
+            if !sink.events().is_empty() && !sink.events().iter().any(|e| e == kind) {
+                continue;
+            }
+            let Some(suppressed) = self.admit(index, event.rate_key(), Instant::now()) else {
+                continue;
+            };
+            let notification = Notification {
+                timestamp: utils::timestamp(),
+                event: &event,
+                message: event.message(),
+                suppressed,
+            };
+            if let Err(e) = self.send(sink, &notification).await {
+                tracing::error!("[通知]: {}发送失败, {:?}", kind, e);
+            }
+        }
+    }
+
+    // 允许发送时返回被限流的数量
+    fn admit(&self, sink: usize, key: String, now: Instant) -> Option<u32> {
+        let mut sent = self.sent.lock().unwrap();
+        match sent.get_mut(&(sink, key.clone())) {
+            Some(state) if now.duration_since(state.last) < self.rate_limit => {
+                state.suppressed += 1;
+                None
+            }
+            Some(state) => {
+                let suppressed = state.suppressed;
+                *state = RateState {
+                    last: now,
+                    suppressed: 0,
+                };
+                Some(suppressed)
+            }
+            None => {
+                sent.insert(
+                    (sink, key),
+                    RateState {
+                        last: now,
+                        suppressed: 0,
+                    },
+                );
+                Some(0)
+            }
+        }
+    }
+
+    async fn send(&self, sink: &NotifySink, notification: &Notification<'_>) -> Result<()> {
+        let body = serde_json::to_string(notification)?;
+        match sink {
+            NotifySink::Webhook { url, .. } => {
+                self.client
+                    .post(url)
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            NotifySink::Command { command, .. } => {
+                let mut child = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("CARDAHOY_EVENT", notification.event.kind())
+                    .env("CARDAHOY_MESSAGE", &notification.message)
+                    .stdin(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(body.as_bytes()).await?;
+                }
+                let status = tokio::time::timeout(COMMAND_TIMEOUT, child.wait()).await??;
+                if !status.success() {
+                    bail!("命令退出码 {}", status);
+                }
+            }
+            NotifySink::Inbox { path, .. } => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", body)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    fn sold() -> Event {
+        Event::ListingSold {
+            card_id: 320,
+            nft_name: "Ammo Crate #1".into(),
+            price: 1.5,
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, utils::timestamp()))
+    }
+
+    #[tokio::test]
+    async fn test_inbox_filter_and_rate_limit() {
+        let path = temp_path("inbox.jsonl");
+        let notifier = Notifier::new(&NotifyConfig {
+            sinks: vec![NotifySink::Inbox {
+                path: path.to_string_lossy().into(),
+                events: vec!["listing_sold".into()],
+            }],
+            rate_limit_secs: 60,
+        });
+
+        notifier
+            .deliver(Event::SessionExpired {
+                reason: "Not Logged".into(),
+            })
+            .await;
+        notifier.deliver(sold()).await;
+        notifier.deliver(sold()).await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["event"], "listing_sold");
+        assert_eq!(json["nft_name"], "Ammo Crate #1");
+
+        // 限流期过后带上被丢弃的数量
+        let later = Instant::now() + Duration::from_secs(61);
+        assert_eq!(
+            notifier.dispatcher.admit(0, "listing_sold".into(), later),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // 读到 JSON 结束即可
+            while !request.ends_with(b"}") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let notifier = Notifier::new(&NotifyConfig {
+            sinks: vec![NotifySink::Webhook {
+                url,
+                events: vec![],
+            }],
+            rate_limit_secs: 0,
+        });
+        // 只入队，由后台任务发送
+        notifier.notify(sold());
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /hook"));
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["event"], "listing_sold");
+        assert_eq!(json["suppressed"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_command() {
+        let path = temp_path("command.json");
+        let notifier = Notifier::new(&NotifyConfig {
+            sinks: vec![NotifySink::Command {
+                command: format!("cat > {}", path.display()),
+                events: vec![],
+            }],
+            rate_limit_secs: 0,
+        });
+        notifier.deliver(sold()).await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["price"], 1.5);
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use api::{analyze, market_home::MarketHomeResponse, nft::NftCardId};
 
 use cardahoy_api as api;
+use cardahoy_utils as utils;
 use chrono::DateTime;
 #[cfg(not(feature = "xlsxwriter"))]
 use csv::Writer;
@@ -12,6 +13,8 @@ use std::{
     io::Write,
 };
 
+use crate::{stats, strategy};
+
 #[cfg(feature = "xlsxwriter")]
 use xlsxwriter::*;
 
@@ -19,9 +22,9 @@ use xlsxwriter::*;
 use calamine::{Reader, Xlsx};
 
 #[allow(dead_code)]
-pub async fn get_all_card() -> Result<()> {
+pub async fn get_all_card(config: &utils::Config) -> Result<()> {
     // sort output.txt
-    let capi = api::CardsAhoyApi::new()?;
+    let capi = api::CardsAhoyApi::new(config)?;
 
     let discrete_list = vec![
         api::filter::Discrete::filter_type(vec![]),
@@ -135,14 +138,14 @@ fn write_excel_from_other_excel(workbook: &Workbook, from: &str) -> Result<()> {
 }
 
 #[cfg(feature = "xlsxwriter")]
-pub async fn get_all_card_deal_trend() -> Result<()> {
+pub async fn get_all_card_deal_trend(config: &utils::Config) -> Result<()> {
     let cards = NftCardId::to_vec_u32();
 
     let file_path = "history_price.xlsx";
     let workbook = Workbook::new("output.xlsx")?;
     write_excel_from_other_excel(&workbook, file_path)?;
 
-    let capi = api::CardsAhoyApi::new()?;
+    let capi = api::CardsAhoyApi::new(config)?;
     let mut results: HashMap<u32, analyze::AnalyzeDealTrendResponse> = HashMap::new();
 
     for card in cards {
@@ -233,10 +236,24 @@ pub async fn get_all_card_deal_trend() -> Result<()> {
     Ok(())
 }
 
+// 实时价格导出中深度一列按此经验计算
+const REALTIME_DEPTH_EXP: u32 = 10;
+
+// 前5张去除离群值后的均价、全部挂单单价的中位数、买入指定经验的单价
+fn realtime_stats(resp: &MarketHomeResponse) -> [String; 3] {
+    let format = |value: Option<f64>| value.map(|v| format!("{:.3}", v)).unwrap_or_default();
+    let prices: Vec<f64> = resp.list.iter().map(strategy::unit_price).collect();
+    [
+        format(strategy::top_n_avg(&resp.list, 5)),
+        format(stats::median(&prices)),
+        format(stats::cost_to_buy(&resp.list, REALTIME_DEPTH_EXP).map(|d| d.price_per_exp())),
+    ]
+}
+
 #[cfg(feature = "xlsxwriter")]
-pub async fn get_all_card_realtime() -> Result<()> {
+pub async fn get_all_card_realtime(config: &utils::Config) -> Result<()> {
     let cards = NftCardId::to_vec_u32();
-    let ca_api = api::CardsAhoyApi::new()?;
+    let ca_api = api::CardsAhoyApi::new(config)?;
     let mut results: HashMap<String, MarketHomeResponse> = HashMap::new();
 
     for card in cards {
@@ -256,7 +273,7 @@ pub async fn get_all_card_realtime() -> Result<()> {
     let workbook = Workbook::new("实时价格.xlsx")?;
     let mut worksheet = workbook.add_worksheet(None)?;
     let mut row_index = 0;
-    let header = vec!["名字", "均价"];
+    let header = vec!["名字", "均价", "中位数", "买入10经验单价"];
     for (col, title) in header.iter().enumerate() {
         worksheet.write_string(0, col as u16, title, None)?;
     }
@@ -264,24 +281,8 @@ pub async fn get_all_card_realtime() -> Result<()> {
     let _ = results
         .iter()
         .map(|(name, resp)| {
-            let sum: f64 = resp
-                .list
-                .iter()
-                .take(5)
-                .filter_map(|ci| {
-                    let price = ci
-                        .metadata_list
-                        .iter()
-                        .find(|meta| meta.name == "Price/EXP")
-                        .map(|meta| meta.value.parse::<f64>().unwrap())
-                        .unwrap_or_else(|| {
-                            ci.sale_price.parse::<f64>().unwrap() / ci.accumulate_trait.value as f64
-                        });
-                    Some(price)
-                })
-                .sum();
-            let avg = sum / 5 as f64;
-            let row = vec![name.clone(), format!("{:.3}", avg)];
+            let [avg, median, depth] = realtime_stats(resp);
+            let row = vec![name.clone(), avg, median, depth];
             for (col, value) in row.iter().enumerate() {
                 let value = format!("{}", value);
                 worksheet.write_string(row_index, col as u16, &value, None)?;
@@ -298,9 +299,9 @@ pub async fn get_all_card_realtime() -> Result<()> {
 }
 
 #[cfg(not(feature = "xlsxwriter"))]
-pub async fn get_all_card_realtime() -> Result<()> {
+pub async fn get_all_card_realtime(config: &utils::Config) -> Result<()> {
     let cards = NftCardId::to_vec_u32();
-    let ca_api = api::CardsAhoyApi::new()?;
+    let ca_api = api::CardsAhoyApi::new(config)?;
     let mut results: HashMap<u32, MarketHomeResponse> = HashMap::new();
 
     for card in cards {
@@ -320,7 +321,7 @@ pub async fn get_all_card_realtime() -> Result<()> {
     let csv_file = File::create("实时价格.csv")?;
     let mut writer = Writer::from_writer(csv_file);
 
-    let headers = ["名字", "英文", "均价"];
+    let headers = ["名字", "英文", "均价", "中位数", "买入10经验单价"];
     writer
         .write_record(&headers)
         .expect("Unable to write header record");
@@ -328,26 +329,10 @@ pub async fn get_all_card_realtime() -> Result<()> {
     let _ = results
         .iter()
         .map(|(card, resp)| {
-            let sum: f64 = resp
-                .list
-                .iter()
-                .take(5)
-                .filter_map(|ci| {
-                    let price = ci
-                        .metadata_list
-                        .iter()
-                        .find(|meta| meta.name == "Price/EXP")
-                        .map(|meta| meta.value.parse::<f64>().unwrap())
-                        .unwrap_or_else(|| {
-                            ci.sale_price.parse::<f64>().unwrap() / ci.accumulate_trait.value as f64
-                        });
-                    Some(price)
-                })
-                .sum();
-            let avg = sum / 5 as f64;
+            let [avg, median, depth] = realtime_stats(resp);
             let cn_name = NftCardId::get_name_by_value(card.clone(), "cn").unwrap();
             let en_name = NftCardId::get_name_by_value(card.clone(), "en").unwrap();
-            let row = [cn_name, en_name, format!("{:.3}", avg)];
+            let row = [cn_name, en_name, avg, median, depth];
             writer.write_record(&row).expect("unable to write record.");
 
             Ok::<_, anyhow::Error>(())
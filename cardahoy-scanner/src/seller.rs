@@ -0,0 +1,544 @@
+use anyhow::Result;
+use cardahoy_api::{game_config::TradeCoin, nft::NftId, CardsAhoyApi};
+use cardahoy_utils as utils;
+
+use crate::ledger::{Ledger, Trade, TradeSide};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Mutex,
+};
+
+const PAGE_SIZE: u32 = 100;
+
+/// A card bought by one of the strategies, waiting to be listed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holding {
+    pub card_id: u32,
+    pub token_id: String,
+    // 买入时的货币单位，按同一货币挂单；旧版本保存的状态中没有，使用默认货币
+    #[serde(default)]
+    pub price_unity: String,
+    pub nft_name: String,
+    pub exp: u32,
+    // 买入价格
+    pub cost: f64,
+    // 买入时的策略
+    pub strategy: String,
+}
+
+/// A card we have put on sale via groundNFTAsset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Listing {
+    pub holding: Holding,
+    pub price: f64,
+    // 挂单时间，毫秒
+    pub listed_at: i64,
+    // 已重新挂单次数
+    pub relists: u32,
+}
+
+/// Lowest price that still makes `markup` over `cost` after the platform and
+/// copyright fees (`fee_rate`, e.g. 0.05 for 5%) are deducted from the sale.
+pub fn break_even_price(cost: f64, markup: f64, fee_rate: f64) -> f64 {
+    cost * (1.0 + markup) / (1.0 - fee_rate)
+}
+
+/// Price for a new listing: never below the markup target, but follow the
+/// market up when it is more expensive. The market price is the lower of the
+/// current lowest listing and the last sale, so a stale high ask does not
+/// leave the card priced above what buyers recently paid.
+pub fn list_price(
+    cost: f64,
+    markup: f64,
+    fee_rate: f64,
+    lowest_price: Option<f64>,
+    last_sale_price: Option<f64>,
+) -> f64 {
+    let target = break_even_price(cost, markup, fee_rate);
+    let market = [lowest_price, last_sale_price]
+        .into_iter()
+        .flatten()
+        .filter(|price| *price > 0.0)
+        .reduce(f64::min);
+    match market {
+        Some(market) if market > target => market,
+        _ => target,
+    }
+}
+
+/// The trade coin for a card bought in `price_unity`, falling back to the
+/// platform default coin when the unit is unknown.
+pub fn trade_coin<'a>(coins: &'a [TradeCoin], price_unity: &str) -> Option<&'a TradeCoin> {
+    coins
+        .iter()
+        .find(|coin| coin.price_unity == price_unity)
+        .or_else(|| coins.iter().find(|coin| coin.if_default))
+}
+
+/// Platform plus copyright fee deducted from a sale in `coin`, e.g. 0.05.
+pub fn fee_rate(coin: &TradeCoin) -> f64 {
+    (coin.platform_fee_percent + coin.copyright_fee_percent) / 100.0
+}
+
+/// Price for a listing that expired without filling, or `None` when it is
+/// already at the minimum markup and should be withdrawn.
+pub fn reprice(price: f64, min_price: f64, step: f64) -> Option<f64> {
+    if price <= min_price {
+        return None;
+    }
+    Some((price * (1.0 - step)).max(min_price))
+}
+
+/// Where an expired listing is found when it is checked again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListingState {
+    // 仍在售，附带下架用的交易编码
+    OnSale(String),
+    // 已回到背包
+    Returned,
+    Sold,
+}
+
+pub fn listing_state(
+    token_id: &str,
+    open_sales: &HashMap<String, String>,
+    owned: &HashSet<String>,
+) -> ListingState {
+    match open_sales.get(token_id) {
+        Some(number) => ListingState::OnSale(number.clone()),
+        None if owned.contains(token_id) => ListingState::Returned,
+        None => ListingState::Sold,
+    }
+}
+
+// 逐页请求直到取完，fetch 返回本页数据与总数
+async fn fetch_all<T, F, Fut>(mut fetch: F) -> Result<Vec<T>>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, u32)>>,
+{
+    let mut items = Vec::new();
+    let mut page = 1;
+    loop {
+        let (list, total) = fetch(page).await?;
+        let count = list.len() as u32;
+        items.extend(list);
+        if count < PAGE_SIZE || page * PAGE_SIZE >= total {
+            break;
+        }
+        page += 1;
+    }
+    Ok(items)
+}
+
+/// Sell-side engine: lists the cards bought by the buy strategies and
+/// reprices listings that did not fill within `ground_expire_minute`.
+///
+/// Once a listing is older than `ground_expire_minute`, it is looked up in the
+/// account's open listings and inventory: listings still on sale are withdrawn
+/// and relisted lower, cards back in the inventory are relisted, and cards in
+/// neither have been sold.
+pub struct Seller {
+    config: utils::SellConfig,
+    holdings: Mutex<Vec<Holding>>,
+    listings: Mutex<Vec<Listing>>,
+}
+
+impl Seller {
+    pub fn new(config: utils::SellConfig) -> Self {
+        Self {
+            config,
+            holdings: Mutex::new(Vec::new()),
+            listings: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add_holding(&self, holding: Holding) {
+        self.holdings.lock().unwrap().push(holding);
+    }
+
+    pub fn listings(&self) -> Vec<Listing> {
+        self.listings.lock().unwrap().clone()
+    }
+
+    pub fn holdings(&self) -> Vec<Holding> {
+        self.holdings.lock().unwrap().clone()
+    }
+
+    /// Picks up the holdings and listings saved by a previous run, so cards
+    /// bought or listed before a restart are still sold and repriced.
+    pub fn restore(&self, holdings: Vec<Holding>, listings: Vec<Listing>) {
+        self.holdings.lock().unwrap().extend(holdings);
+        self.listings.lock().unwrap().extend(listings);
+    }
+
+    // 可用的交易货币与挂单有效期（毫秒）
+    async fn coins_and_expire(&self, api: &CardsAhoyApi) -> Result<(Vec<TradeCoin>, i64)> {
+        let game_config = api.query_game_config().await?;
+        let expire = game_config.ground_expire_minute as i64 * 60 * 1000;
+        Ok((game_config.trade_coin_list, expire))
+    }
+
+    /// Lists every pending holding and reprices expired listings. Returns the
+    /// sales found and recorded in this cycle.
+    pub async fn run_cycle(&self, api: &CardsAhoyApi, ledger: &Ledger) -> Result<Vec<Trade>> {
+        // 处理完一项才从待处理中移除，中途出错或退出时保存的状态仍包含它
+        let holdings = self.holdings();
+        if holdings.is_empty() && self.listings.lock().unwrap().is_empty() {
+            return Ok(vec![]);
+        }
+
+        let (coins, expire) = self.coins_and_expire(api).await?;
+
+        for holding in holdings {
+            let Some(coin) = trade_coin(&coins, &holding.price_unity) else {
+                tracing::error!(
+                    "[卖出]: 没有{}对应的交易货币, 暂不挂单{}",
+                    holding.price_unity,
+                    holding.nft_name
+                );
+                continue;
+            };
+            let range = api
+                .query_sell_nft_detail(NftId::Cards, vec![holding.token_id.clone()], coin.coin_id)
+                .await
+                .ok();
+            let lowest = range
+                .as_ref()
+                .and_then(|range| range.lowest_price.parse::<f64>().ok());
+            let last_sale = range
+                .as_ref()
+                .and_then(|range| range.last_sale_price.as_ref()?.parse::<f64>().ok());
+            let price = list_price(
+                holding.cost,
+                self.config.markup,
+                fee_rate(coin),
+                lowest,
+                last_sale,
+            );
+            let listed = api.ground_nft_asset(price, holding.token_id.clone(), coin.coin_id);
+            let _ = self.list_holding(holding, price, listed).await;
+        }
+
+        let now = utils::timestamp();
+        // 挂单时已确认货币存在；游戏配置变化后找不到时无法查询在售挂单，暂不处理
+        let expired: Vec<(Listing, &TradeCoin)> = self
+            .listings()
+            .into_iter()
+            .filter(|listing| now - listing.listed_at >= expire)
+            .filter_map(
+                |listing| match trade_coin(&coins, &listing.holding.price_unity) {
+                    Some(coin) => Some((listing, coin)),
+                    None => {
+                        tracing::error!(
+                            "[卖出]: 没有{}对应的交易货币, 暂不检查挂单{}",
+                            listing.holding.price_unity,
+                            listing.holding.nft_name
+                        );
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        if expired.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // 在售挂单按货币分别查询，每种用到的货币都要查
+        let coin_ids: HashSet<u32> = expired.iter().map(|(_, coin)| coin.coin_id).collect();
+        let open_sales = self.open_sales(api, &coin_ids).await?;
+        let owned = self.owned_token_ids(api).await?;
+
+        let mut sold = Vec::new();
+        for (listing, coin) in expired {
+            let fee_rate = fee_rate(coin);
+            match listing_state(&listing.holding.token_id, &open_sales, &owned) {
+                // 仍在售，先下架再重新定价
+                ListingState::OnSale(number) => {
+                    if let Err(e) = api.cancel_ground_nft_asset(&number).await {
+                        tracing::error!("[卖出]: {}下架失败", listing.holding.nft_name);
+                        tracing::error!("{:?}", e);
+                        continue;
+                    }
+                    self.remove_listing(&listing.holding.token_id);
+                }
+                ListingState::Returned => self.remove_listing(&listing.holding.token_id),
+                ListingState::Sold => {
+                    self.remove_listing(&listing.holding.token_id);
+                    tracing::info!(
+                        "[卖出]: {}已成交, 价格为{}",
+                        listing.holding.nft_name,
+                        listing.price
+                    );
+                    let holding = listing.holding;
+                    let trade = Trade {
+                        side: TradeSide::Sell,
+                        card_id: holding.card_id,
+                        token_id: holding.token_id,
+                        nft_name: holding.nft_name,
+                        exp: holding.exp,
+                        price: listing.price,
+                        fee: listing.price * fee_rate,
+                        timestamp: utils::timestamp(),
+                        strategy: holding.strategy,
+                    };
+                    // 记录失败也继续处理其余到期挂单，避免它们被丢弃
+                    if let Err(e) = ledger.record(trade.clone()) {
+                        tracing::error!("[账本]: 记录{}失败, {:?}", trade.nft_name, e);
+                    }
+                    sold.push(trade);
+                    continue;
+                }
+            }
+
+            let min_price =
+                break_even_price(listing.holding.cost, self.config.min_markup, fee_rate);
+            match reprice(listing.price, min_price, self.config.reprice_step) {
+                Some(price) if listing.relists < self.config.max_relists => {
+                    tracing::info!(
+                        "[卖出]: {}挂单未成交, 降价 {} -> {}",
+                        listing.holding.nft_name,
+                        listing.price,
+                        price
+                    );
+                    let listed =
+                        api.ground_nft_asset(price, listing.holding.token_id.clone(), coin.coin_id);
+                    let _ = self.relist(listing, price, listed).await;
+                }
+                _ => {
+                    tracing::warn!(
+                        "[卖出]: {}多次挂单未成交, 不再挂单, 价格:{}",
+                        listing.holding.nft_name,
+                        listing.price
+                    );
+                }
+            }
+        }
+
+        Ok(sold)
+    }
+
+    fn remove_listing(&self, token_id: &str) {
+        self.listings
+            .lock()
+            .unwrap()
+            .retain(|listing| listing.holding.token_id != token_id);
+    }
+
+    async fn owned_token_ids(&self, api: &CardsAhoyApi) -> Result<HashSet<String>> {
+        let token_ids = fetch_all(|page| async move {
+            let resp = api
+                .query_user_nft_list(NftId::Cards, page, PAGE_SIZE)
+                .await?;
+            let list = resp.list.into_iter().map(|nft| nft.token_id).collect();
+            Ok((list, resp.total))
+        })
+        .await?;
+        Ok(token_ids.into_iter().collect())
+    }
+
+    // 在售挂单 token_id -> 交易编码，包含 coin_ids 中每种货币的挂单
+    async fn open_sales(
+        &self,
+        api: &CardsAhoyApi,
+        coin_ids: &HashSet<u32>,
+    ) -> Result<HashMap<String, String>> {
+        let mut open_sales = HashMap::new();
+        for &coin_id in coin_ids {
+            let sales = fetch_all(|page| async move {
+                let resp = api
+                    .query_user_sale_list(NftId::Cards, coin_id, page, PAGE_SIZE)
+                    .await?;
+                let list = resp
+                    .list
+                    .into_iter()
+                    .map(|sale| (sale.token_id, sale.sale_aggregator_number))
+                    .collect();
+                Ok((list, resp.total))
+            })
+            .await?;
+            open_sales.extend(sales);
+        }
+        Ok(open_sales)
+    }
+
+    // 挂单成功后才从待挂单中移除，失败的下一轮再试
+    async fn list_holding(
+        &self,
+        holding: Holding,
+        price: f64,
+        listed: impl Future<Output = Result<String>>,
+    ) -> Result<()> {
+        self.ground(&holding, price, 0, listed).await?;
+        self.holdings
+            .lock()
+            .unwrap()
+            .retain(|h| h.token_id != holding.token_id);
+        Ok(())
+    }
+
+    // 重新挂单已下架或退回的卡牌，失败时放回待挂单，下一轮按新价格挂单
+    async fn relist(
+        &self,
+        listing: Listing,
+        price: f64,
+        listed: impl Future<Output = Result<String>>,
+    ) -> Result<()> {
+        let result = self
+            .ground(&listing.holding, price, listing.relists + 1, listed)
+            .await;
+        if result.is_err() {
+            self.add_holding(listing.holding);
+        }
+        result
+    }
+
+    // 挂单成功后才记录为挂单
+    async fn ground(
+        &self,
+        holding: &Holding,
+        price: f64,
+        relists: u32,
+        listed: impl Future<Output = Result<String>>,
+    ) -> Result<()> {
+        if let Err(e) = listed.await {
+            tracing::error!("[卖出]: {}挂单失败", holding.nft_name);
+            tracing::error!("{:?}", e);
+            return Err(e);
+        }
+        tracing::info!("[卖出]: {}挂单成功, 价格为{}", holding.nft_name, price);
+        self.listings.lock().unwrap().push(Listing {
+            holding: holding.clone(),
+            price,
+            listed_at: utils::timestamp(),
+            relists,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_price() {
+        let target = break_even_price(1.0, 0.2, 0.04);
+        assert!((target - 1.25).abs() < 1e-9);
+        assert!((list_price(1.0, 0.2, 0.04, Some(1.1), None) - 1.25).abs() < 1e-9);
+        assert!((list_price(1.0, 0.2, 0.04, Some(1.5), None) - 1.5).abs() < 1e-9);
+        assert!((list_price(1.0, 0.2, 0.04, None, None) - 1.25).abs() < 1e-9);
+        // 最近成交低于最低挂单时按成交价，但不低于目标价
+        assert!((list_price(1.0, 0.2, 0.04, Some(1.5), Some(1.4)) - 1.4).abs() < 1e-9);
+        assert!((list_price(1.0, 0.2, 0.04, Some(1.5), Some(1.0)) - 1.25).abs() < 1e-9);
+        assert!((list_price(1.0, 0.2, 0.04, None, Some(1.4)) - 1.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trade_coin() {
+        let coins: Vec<TradeCoin> = serde_json::from_value(serde_json::json!([
+            {
+                "coinId": 1, "decimals": 18, "coinUrl": "", "ifDefault": true,
+                "priceUnity": "USDT", "coinType": null,
+                "platformFeePercent": 4.0, "copyrightFeePercent": 1.0
+            },
+            {
+                "coinId": 2, "decimals": 18, "coinUrl": "", "ifDefault": false,
+                "priceUnity": "MATIC", "coinType": null,
+                "platformFeePercent": 2.0, "copyrightFeePercent": 0.0
+            }
+        ]))
+        .unwrap();
+        let coin = trade_coin(&coins, "MATIC").unwrap();
+        assert_eq!(coin.coin_id, 2);
+        assert!((fee_rate(coin) - 0.02).abs() < 1e-9);
+        // 未知或旧状态中为空的货币使用默认货币
+        assert_eq!(trade_coin(&coins, "").unwrap().coin_id, 1);
+        assert!(trade_coin(&[], "USDT").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_sales_second_page() {
+        // 第一页满 PAGE_SIZE 条，目标挂单在第二页
+        let pages: Vec<Vec<(String, String)>> = vec![
+            (0..PAGE_SIZE)
+                .map(|i| (format!("token-{}", i), format!("SA{}", i)))
+                .collect(),
+            vec![("expired".to_string(), "SA-expired".to_string())],
+        ];
+        let total = PAGE_SIZE + 1;
+        let pages = &pages;
+        let open_sales: HashMap<String, String> =
+            fetch_all(|page| async move { Ok((pages[page as usize - 1].clone(), total)) })
+                .await
+                .unwrap()
+                .into_iter()
+                .collect();
+        assert_eq!(open_sales.len(), total as usize);
+
+        let owned = HashSet::from(["returned".to_string()]);
+        assert_eq!(
+            listing_state("expired", &open_sales, &owned),
+            ListingState::OnSale("SA-expired".into())
+        );
+        assert_eq!(
+            listing_state("returned", &open_sales, &owned),
+            ListingState::Returned
+        );
+        assert_eq!(
+            listing_state("gone", &open_sales, &owned),
+            ListingState::Sold
+        );
+    }
+
+    fn holding(token_id: &str) -> Holding {
+        Holding {
+            card_id: 320,
+            token_id: token_id.into(),
+            price_unity: "USDT".into(),
+            nft_name: format!("Axe #{}", token_id),
+            exp: 1,
+            cost: 1.0,
+            strategy: "cheap_cards".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_listing_is_kept() {
+        let seller = Seller::new(utils::SellConfig::default());
+        let failed = || async { Err::<String, _>(anyhow::anyhow!("network error")) };
+
+        // 挂单失败时仍在待挂单中
+        seller.add_holding(holding("1"));
+        assert!(seller
+            .list_holding(holding("1"), 1.25, failed())
+            .await
+            .is_err());
+        assert_eq!(seller.holdings().len(), 1);
+        assert!(seller.listings().is_empty());
+
+        seller
+            .list_holding(holding("1"), 1.25, async { Ok("ok".to_string()) })
+            .await
+            .unwrap();
+        assert!(seller.holdings().is_empty());
+        let listing = seller.listings().remove(0);
+        assert_eq!(listing.relists, 0);
+
+        // 下架后重新挂单失败，放回待挂单
+        seller.remove_listing(&listing.holding.token_id);
+        assert!(seller.relist(listing, 1.2, failed()).await.is_err());
+        assert!(seller.listings().is_empty());
+        assert_eq!(seller.holdings()[0].token_id, "1");
+    }
+
+    #[test]
+    fn test_reprice() {
+        assert_eq!(reprice(1.0, 0.5, 0.1), Some(0.9));
+        assert_eq!(reprice(0.52, 0.5, 0.1), Some(0.5));
+        assert_eq!(reprice(0.5, 0.5, 0.1), None);
+    }
+}
@@ -0,0 +1,338 @@
+use cardahoy_api::{
+    market_home::{CardInformation, MarketHomeResponse},
+    market_secondary::MarketSecondaryResponse,
+    nft::NftCardId,
+};
+use comfy_table::Table;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::history::{Snapshot, SnapshotData};
+use crate::strategy::unit_price;
+
+/// One card's row in the secondary market listing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardSummary {
+    // 底价 Price/EXP，无在售时为空
+    pub floor: Option<f64>,
+    // 在售数量
+    pub quantity: u32,
+    // 累计成交数量
+    pub volume: u32,
+}
+
+/// The whole market at one point in time: the secondary listing for every
+/// card, plus the order books that were scanned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    // 毫秒时间戳
+    pub timestamp: i64,
+    pub cards: BTreeMap<u32, CardSummary>,
+    // 按卡牌 id 的挂单列表，只包含扫描过的卡牌
+    pub books: BTreeMap<u32, Vec<CardInformation>>,
+}
+
+impl MarketSnapshot {
+    pub fn new(timestamp: i64) -> Self {
+        Self {
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_secondary(&mut self, response: &MarketSecondaryResponse) {
+        for card in &response.list {
+            self.cards.insert(
+                card.secondary_id,
+                CardSummary {
+                    floor: card.floor_price.parse::<f64>().ok(),
+                    quantity: card.quantity,
+                    volume: card.volume,
+                },
+            );
+        }
+    }
+
+    pub fn set_book(&mut self, card_id: u32, response: &MarketHomeResponse) {
+        self.books.insert(card_id, response.list.clone());
+    }
+
+    /// Folds a recorded response into the snapshot.
+    pub fn apply(&mut self, snapshot: &Snapshot) {
+        self.timestamp = snapshot.timestamp;
+        match &snapshot.data {
+            SnapshotData::Secondary { response } => self.set_secondary(response),
+            SnapshotData::Home { card_id, response } => self.set_book(*card_id, response),
+        }
+    }
+
+    /// Rebuilds market snapshots from recorded history, one per secondary
+    /// listing record. Order books carry over until they are scanned again.
+    pub fn replay(history: &[Snapshot]) -> Vec<MarketSnapshot> {
+        let mut current = MarketSnapshot::default();
+        let mut snapshots = Vec::new();
+        for snapshot in history {
+            current.apply(snapshot);
+            if matches!(snapshot.data, SnapshotData::Secondary { .. }) {
+                snapshots.push(current.clone());
+            }
+        }
+        snapshots
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingChange {
+    pub card_id: u32,
+    pub sale_aggregator_number: String,
+    pub nft_name: String,
+    pub exp: u32,
+    pub price: f64,
+}
+
+impl ListingChange {
+    fn new(card_id: u32, ci: &CardInformation) -> Self {
+        Self {
+            card_id,
+            sale_aggregator_number: ci.sale_aggregator_number.clone(),
+            nft_name: ci.nft_name.clone(),
+            exp: ci.accumulate_trait.value,
+            price: ci.sale_price.parse::<f64>().unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloorMove {
+    pub card_id: u32,
+    pub before: f64,
+    pub after: f64,
+}
+
+impl FloorMove {
+    /// Relative change, e.g. 0.1 for a 10% rise.
+    pub fn change(&self) -> f64 {
+        (self.after - self.before) / self.before
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeDelta {
+    pub card_id: u32,
+    // 两次快照之间新增的成交数量
+    pub volume: u32,
+    pub quantity_before: u32,
+    pub quantity_after: u32,
+}
+
+/// What changed in the market between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketDiff {
+    pub from: i64,
+    pub to: i64,
+    pub new_listings: Vec<ListingChange>,
+    // 卖出或下架的挂单
+    pub removed_listings: Vec<ListingChange>,
+    pub floor_moves: Vec<FloorMove>,
+    pub volume_deltas: Vec<VolumeDelta>,
+}
+
+impl MarketDiff {
+    /// Total cards sold between the snapshots, from the volume counters.
+    pub fn sold(&self) -> u32 {
+        self.volume_deltas.iter().map(|delta| delta.volume).sum()
+    }
+}
+
+/// Compares two snapshots.
+///
+/// Order books are only compared for cards scanned in both. The market home
+/// query returns only the cheapest page, so a listing missing from `after`
+/// counts as removed only if it was priced within the range `after` still
+/// shows; otherwise it may just have been pushed off the page.
+pub fn diff(before: &MarketSnapshot, after: &MarketSnapshot) -> MarketDiff {
+    let mut result = MarketDiff {
+        from: before.timestamp,
+        to: after.timestamp,
+        ..Default::default()
+    };
+
+    for (card_id, now) in &after.cards {
+        let Some(was) = before.cards.get(card_id) else {
+            continue;
+        };
+        if let (Some(before), Some(after)) = (was.floor, now.floor) {
+            if before != after && before > 0.0 {
+                result.floor_moves.push(FloorMove {
+                    card_id: *card_id,
+                    before,
+                    after,
+                });
+            }
+        }
+        let volume = now.volume.saturating_sub(was.volume);
+        if volume > 0 || was.quantity != now.quantity {
+            result.volume_deltas.push(VolumeDelta {
+                card_id: *card_id,
+                volume,
+                quantity_before: was.quantity,
+                quantity_after: now.quantity,
+            });
+        }
+    }
+
+    for (card_id, book) in &after.books {
+        let Some(old) = before.books.get(card_id) else {
+            continue;
+        };
+        let old_numbers: HashSet<&str> = old
+            .iter()
+            .map(|ci| ci.sale_aggregator_number.as_str())
+            .collect();
+        let new_numbers: HashSet<&str> = book
+            .iter()
+            .map(|ci| ci.sale_aggregator_number.as_str())
+            .collect();
+        let visible = book.iter().map(unit_price).reduce(f64::max);
+
+        result.new_listings.extend(
+            book.iter()
+                .filter(|ci| !old_numbers.contains(ci.sale_aggregator_number.as_str()))
+                .map(|ci| ListingChange::new(*card_id, ci)),
+        );
+        result.removed_listings.extend(
+            old.iter()
+                .filter(|ci| !new_numbers.contains(ci.sale_aggregator_number.as_str()))
+                .filter(|ci| visible.is_none_or(|max| unit_price(ci) <= max))
+                .map(|ci| ListingChange::new(*card_id, ci)),
+        );
+    }
+
+    result
+}
+
+pub fn print_diff(diff: &MarketDiff) {
+    let name = |card_id: u32| {
+        NftCardId::get_name_by_value(card_id, "cn").unwrap_or_else(|| card_id.to_string())
+    };
+
+    let mut table = Table::new();
+    table.set_header(vec!["卡牌", "底价变化", "涨跌", "成交", "在售"]);
+    let mut rows: BTreeMap<u32, [String; 4]> = BTreeMap::new();
+    for floor in &diff.floor_moves {
+        let row = rows.entry(floor.card_id).or_default();
+        row[0] = format!("{:.4} -> {:.4}", floor.before, floor.after);
+        row[1] = format!("{:+.2}%", floor.change() * 100.0);
+    }
+    for delta in &diff.volume_deltas {
+        let row = rows.entry(delta.card_id).or_default();
+        row[2] = delta.volume.to_string();
+        row[3] = format!("{} -> {}", delta.quantity_before, delta.quantity_after);
+    }
+    for (card_id, row) in rows {
+        let mut cells = vec![name(card_id)];
+        cells.extend(row);
+        table.add_row(cells);
+    }
+    println!("{table}");
+    println!(
+        "新挂单: {}, 卖出或下架: {}, 成交: {}",
+        diff.new_listings.len(),
+        diff.removed_listings.len(),
+        diff.sold()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::tests::listing;
+
+    fn snapshot(
+        timestamp: i64,
+        floor: f64,
+        volume: u32,
+        book: Vec<CardInformation>,
+    ) -> MarketSnapshot {
+        let mut snapshot = MarketSnapshot::new(timestamp);
+        snapshot.cards.insert(
+            320,
+            CardSummary {
+                floor: Some(floor),
+                quantity: book.len() as u32,
+                volume,
+            },
+        );
+        snapshot.books.insert(320, book);
+        snapshot
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = snapshot(
+            1,
+            0.1,
+            10,
+            vec![
+                listing("a", 0.1, 1),
+                listing("b", 0.2, 1),
+                listing("c", 0.5, 1),
+            ],
+        );
+        let after = snapshot(
+            2,
+            0.15,
+            12,
+            vec![listing("b", 0.2, 1), listing("d", 0.3, 1)],
+        );
+        let diff = diff(&before, &after);
+
+        let numbers = |list: &[ListingChange]| {
+            list.iter()
+                .map(|l| l.sale_aggregator_number.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(&diff.new_listings), vec!["d"]);
+        // c 高于 after 中可见的最高价，可能只是被挤出第一页
+        assert_eq!(numbers(&diff.removed_listings), vec!["a"]);
+        assert_eq!(diff.floor_moves.len(), 1);
+        assert!((diff.floor_moves[0].change() - 0.5).abs() < 1e-9);
+        assert_eq!(diff.sold(), 2);
+    }
+
+    #[test]
+    fn test_replay() {
+        let home = MarketHomeResponse {
+            list: vec![listing("a", 0.1, 1)],
+        };
+        let secondary: MarketSecondaryResponse = serde_json::from_value(serde_json::json!({
+            "total": 1,
+            "list": [{
+                "volume": 3, "quantity": 1, "secondaryId": 320, "secondaryName": "AmmoCrate",
+                "nftName": "Cards", "image": "", "chainNftId": 12,
+                "floorPrice": "0.1", "priceUnity": "USDT"
+            }]
+        }))
+        .unwrap();
+        let history = vec![
+            Snapshot {
+                timestamp: 1,
+                data: SnapshotData::Home {
+                    card_id: 320,
+                    response: home,
+                },
+            },
+            Snapshot {
+                timestamp: 2,
+                data: SnapshotData::Secondary {
+                    response: secondary,
+                },
+            },
+        ];
+        let snapshots = MarketSnapshot::replay(&history);
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].timestamp, 2);
+        assert_eq!(snapshots[0].books[&320].len(), 1);
+        assert_eq!(snapshots[0].cards[&320].volume, 3);
+    }
+}
@@ -0,0 +1,176 @@
+use cardahoy_api::market_home::CardInformation;
+
+use crate::strategy::unit_price;
+
+// 修正 z 分数超过此值视为离群值
+pub const OUTLIER_THRESHOLD: f64 = 3.5;
+
+fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    values.sort_by(|a, b| a.total_cmp(b));
+    values
+}
+
+/// Arithmetic mean over the finite values, dividing by how many there are.
+pub fn mean(values: &[f64]) -> Option<f64> {
+    let values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Mean after dropping `trim` (0.0..0.5) of the values from each end.
+pub fn trimmed_mean(values: &[f64], trim: f64) -> Option<f64> {
+    let values = sorted(values);
+    let cut = (values.len() as f64 * trim.clamp(0.0, 0.49)).floor() as usize;
+    mean(&values[cut..values.len() - cut])
+}
+
+pub fn median(values: &[f64]) -> Option<f64> {
+    percentile(values, 50.0)
+}
+
+/// Linearly interpolated percentile, `p` in 0..=100.
+pub fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    let values = sorted(values);
+    if values.is_empty() {
+        return None;
+    }
+    let rank = p.clamp(0.0, 100.0) / 100.0 * (values.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(values[lower] + (values[upper] - values[lower]) * (rank - lower as f64))
+}
+
+/// Median absolute deviation from the median.
+pub fn mad(values: &[f64]) -> Option<f64> {
+    let center = median(values)?;
+    let deviations: Vec<f64> = values.iter().map(|v| (v - center).abs()).collect();
+    median(&deviations)
+}
+
+/// Flags values whose modified z-score exceeds `threshold`.
+///
+/// When more than half the values are identical the MAD is zero, and any
+/// value off the median is flagged.
+pub fn outliers(values: &[f64], threshold: f64) -> Vec<bool> {
+    let (Some(center), Some(mad)) = (median(values), mad(values)) else {
+        return vec![true; values.len()];
+    };
+    values
+        .iter()
+        .map(|v| {
+            if !v.is_finite() {
+                return true;
+            }
+            let deviation = (v - center).abs();
+            if mad == 0.0 {
+                deviation > f64::EPSILON
+            } else {
+                0.6745 * deviation / mad > threshold
+            }
+        })
+        .collect()
+}
+
+/// The values that are not outliers, in their original order.
+pub fn without_outliers(values: &[f64], threshold: f64) -> Vec<f64> {
+    values
+        .iter()
+        .zip(outliers(values, threshold))
+        .filter(|(_, outlier)| !outlier)
+        .map(|(v, _)| *v)
+        .collect()
+}
+
+/// What it takes to accumulate a given amount of EXP from an order book.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Depth {
+    // 实际买到的经验，可能略多于目标
+    pub exp: u32,
+    pub cost: f64,
+    pub listings: usize,
+}
+
+impl Depth {
+    pub fn price_per_exp(&self) -> f64 {
+        self.cost / self.exp.max(1) as f64
+    }
+}
+
+/// Cost to buy at least `exp` EXP taking the cheapest listings by unit
+/// price first. `None` if the book is not deep enough.
+pub fn cost_to_buy(list: &[CardInformation], exp: u32) -> Option<Depth> {
+    let mut book: Vec<(f64, f64, u32)> = list
+        .iter()
+        .filter_map(|ci| {
+            let price = ci.sale_price.parse::<f64>().ok()?;
+            Some((unit_price(ci), price, ci.accumulate_trait.value.max(1)))
+        })
+        .filter(|(unit, _, _)| unit.is_finite())
+        .collect();
+    book.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut depth = Depth {
+        exp: 0,
+        cost: 0.0,
+        listings: 0,
+    };
+    for (_, price, card_exp) in book {
+        if depth.exp >= exp {
+            break;
+        }
+        depth.exp += card_exp;
+        depth.cost += price;
+        depth.listings += 1;
+    }
+    (depth.exp >= exp).then_some(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::tests::listing;
+
+    #[test]
+    fn test_central_tendency() {
+        let values = [1.0, 2.0, 3.0, 4.0, 100.0];
+        assert_eq!(mean(&[1.0, 2.0]), Some(1.5));
+        assert_eq!(mean(&[]), None);
+        assert_eq!(median(&values), Some(3.0));
+        assert_eq!(median(&[1.0, 2.0, 3.0, 4.0]), Some(2.5));
+        assert_eq!(trimmed_mean(&values, 0.2), Some(3.0));
+        assert_eq!(percentile(&values, 25.0), Some(2.0));
+        assert_eq!(percentile(&values, 100.0), Some(100.0));
+    }
+
+    #[test]
+    fn test_outliers() {
+        let values = [1.0, 1.1, 0.9, 1.0, 5.0];
+        assert!((mad(&values).unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(
+            outliers(&values, OUTLIER_THRESHOLD),
+            vec![false, false, false, false, true]
+        );
+        assert_eq!(
+            without_outliers(&[0.4, 1.0, 1.0, 1.0, 1.0], OUTLIER_THRESHOLD),
+            vec![1.0; 4]
+        );
+    }
+
+    #[test]
+    fn test_cost_to_buy() {
+        let list = vec![
+            listing("a", 0.5, 4),
+            listing("b", 0.2, 1),
+            listing("c", 0.3, 2),
+        ];
+        let depth = cost_to_buy(&list, 3).unwrap();
+        assert_eq!((depth.exp, depth.listings), (3, 2));
+        assert!((depth.cost - 0.8).abs() < 1e-9);
+
+        let depth = cost_to_buy(&list, 5).unwrap();
+        assert_eq!(depth.exp, 7);
+        assert!(cost_to_buy(&list, 8).is_none());
+    }
+}
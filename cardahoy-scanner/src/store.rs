@@ -1,24 +1,36 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use api::market_secondary::Secondary;
-use api::nft::{filter_cards, NftCardColor};
+use api::nft::filter_cards;
 use cardahoy_api as api;
 use cardahoy_api::{
-    market_home::MarketHomeResponse,
+    market_home::{CardInformation, MarketHomeResponse},
     market_secondary::MarketSecondaryResponse,
     nft::{get_card_by_name, NftCardId, NftId, NftSortType},
     CardsAhoyApi,
 };
 use cardahoy_utils as utils;
+use chrono::{Local, NaiveTime};
 use comfy_table::Table;
 use csv::ReaderBuilder;
 use futures::stream::StreamExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 use tokio::time::Duration;
 
-const CARD_NUMBER_GREATER: usize = 10;
-const CARD_TOP_N: usize = 5;
-const CARD_GOLD_TOP_N: usize = 3;
+use crate::alert::Alert;
+use crate::check;
+use crate::control::{Control, ParamsUpdate, Status};
+use crate::feed::MarketFeed;
+use crate::ledger::{Ledger, Trade, TradeSide};
+use crate::notify::{Event, Notifier};
+use crate::seller::{Holding, Listing, Seller};
+use crate::strategy::{self, Strategy, StrategyParams};
+use crate::telemetry;
+use crate::tracker::{OrderTracker, TrackedOrder};
+use crate::valuation::FairValueModel;
+use crate::verify::{verify_listing, Rejection};
 
 pub fn read_csv(filename: &str) -> Result<HashMap<u32, f64>> {
     let mut map: HashMap<u32, f64> = HashMap::new();
@@ -38,26 +50,174 @@ pub fn read_csv(filename: &str) -> Result<HashMap<u32, f64>> {
     Ok(map)
 }
 
+// 本地时间当天零点的毫秒时间戳
+fn start_of_today() -> i64 {
+    Local::now()
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .map_or(0, |start| start.timestamp_millis())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct FloorPriceRecord {
     key: String,
     value: f64,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum BuyOutcome {
+    Bought,
+    // 该挂单已经尝试过（进行中、成功或失败），本次跳过
+    Skipped,
+    // 购买前复核挂单详情不通过
+    Rejected(Rejection),
+    // 已通过控制接口暂停购买
+    Paused,
+    // 守护进程正在退出，不再开始新的订单
+    Stopping,
+}
+
+// 状态接口返回的最近交易数量
+const RECENT_ORDERS: usize = 20;
+// 成交记录变化较慢，两次拉取之间至少间隔
+const DEALS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// What the store keeps across restarts, written to `state_path`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreState {
+    pub holdings: Vec<Holding>,
+    pub listings: Vec<Listing>,
+    pub orders: Vec<TrackedOrder>,
+}
+
+impl StoreState {
+    pub fn load(path: &str) -> Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(
+                serde_json::from_str(&contents)
+                    .with_context(|| format!("状态文件 {} 无效", path))?,
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("无法读取状态文件 {}", path)),
+        }
+    }
+
+    // 先写临时文件再替换，避免中途退出留下不完整的状态
+    pub fn save(&self, path: &str) -> Result<()> {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Prints every gold/regular spread, cheapest gold relative to regular first.
+pub fn print_gold_spreads(spreads: &mut [strategy::GoldSpread]) {
+    spreads.sort_by(|a, b| {
+        let a = a.spread().unwrap_or(f64::INFINITY);
+        let b = b.spread().unwrap_or(f64::INFINITY);
+        a.total_cmp(&b)
+    });
+    let format = |value: Option<f64>| value.map(|v| format!("{:.4}", v)).unwrap_or_default();
+
+    let mut table = Table::new();
+    table.set_header(vec![
+        "金卡",
+        "稀有度",
+        "普卡均价",
+        "金卡最低",
+        "价差",
+        "阈值",
+    ]);
+    for spread in spreads.iter() {
+        table.add_row(vec![
+            spread.gold.to_chinese().to_string(),
+            spread.rarity.to_string(),
+            format(spread.regular_avg),
+            format(spread.gold_floor),
+            format(spread.spread()),
+            format!("{:.2}", spread.ratio),
+        ]);
+    }
+    println!("{table}");
+}
+
 pub struct Store {
-    pub api: CardsAhoyApi,
+    pub api: Arc<CardsAhoyApi>,
     pub prices: HashMap<u32, f64>,
     pub config: utils::Config,
     pub cards_number: u32,
     pub discrete_list: Vec<api::filter::Discrete>,
+    // 下单记录，多账户时共用，避免多个账户抢同一个挂单
+    pub tracker: Arc<OrderTracker>,
+    pub seller: Seller,
+    pub ledger: Ledger,
+    // 行情数据，多账户时共用
+    pub feed: Arc<MarketFeed>,
+    pub valuation: FairValueModel,
+    // 上次拉取成交记录的时间
+    deals_observed: Mutex<Option<Instant>>,
+    // 最近一次发送每日预算通知的日期（当天零点），每天只通知一次
+    budget_notified: Mutex<Option<i64>>,
+    pub notifier: Notifier,
+    // 策略阈值，可通过控制接口在运行时修改
+    params: RwLock<StrategyParams>,
+    pub control: Control,
 }
 
 impl Store {
-    pub fn new() -> Result<Self> {
-        let api = CardsAhoyApi::new()?;
-        let prices: HashMap<u32, f64> = read_csv("neutral.csv")?;
-        let config = utils::Config::new();
+    pub fn new(config: utils::Config) -> Result<Self> {
+        let feed = Self::market_feed(&config)?;
+        Self::with_shared(config, feed, Arc::default())
+    }
+
+    /// A store for one account that reads the market through a shared `feed`
+    /// and records its orders in a shared `tracker`, so accounts never race
+    /// for the same listing.
+    pub fn with_shared(
+        config: utils::Config,
+        feed: Arc<MarketFeed>,
+        tracker: Arc<OrderTracker>,
+    ) -> Result<Self> {
+        let api = Arc::new(CardsAhoyApi::new(&config)?);
+        let prices = read_csv("neutral.csv")?;
+        Self::build(config, api, feed, tracker, prices)
+    }
+
+    /// One store per configured profile, all sharing one order tracker and
+    /// the market feed built from the first profile.
+    pub fn profiles(config: &utils::Config) -> Result<Vec<(String, Store)>> {
+        let mut stores: Vec<(String, Store)> = Vec::new();
+        let tracker = Arc::new(OrderTracker::default());
+        for name in config.profiles.keys() {
+            let profile = config.profile(name)?;
+            let store = match stores.first() {
+                Some((_, first)) => Ok(first.feed.clone()),
+                None => Self::market_feed(&profile),
+            }
+            .and_then(|feed| Self::with_shared(profile, feed, tracker.clone()))
+            .with_context(|| format!("账户 {} 初始化失败", name))?;
+            stores.push((name.clone(), store));
+        }
+        Ok(stores)
+    }
 
+    // 行情使用单独的客户端，扫描不占用下单请求的间隔
+    fn market_feed(config: &utils::Config) -> Result<Arc<MarketFeed>> {
+        let api = Arc::new(CardsAhoyApi::new(config)?);
+        Ok(Arc::new(MarketFeed::new(api, config)?))
+    }
+
+    fn build(
+        config: utils::Config,
+        api: Arc<CardsAhoyApi>,
+        feed: Arc<MarketFeed>,
+        tracker: Arc<OrderTracker>,
+        prices: HashMap<u32, f64>,
+    ) -> Result<Self> {
         let discrete_list = vec![
             api::filter::Discrete::with_none(api::filter::DiscreteFilter::Type),
             api::filter::Discrete::faction(config.faction.clone()),
@@ -66,46 +226,29 @@ impl Store {
             api::filter::Discrete::with_none(api::filter::DiscreteFilter::Source),
         ];
 
-        let categories: HashSet<api::nft::NftCardCategory> = config
-            .faction
-            .iter()
-            .map(|faction| {
-                let faction = faction.as_str();
-                match faction {
-                    "Neutral" => api::nft::NftCardCategory::Neutral,
-                    "Zombie" => api::nft::NftCardCategory::Zombie,
-                    "Dragon" => api::nft::NftCardCategory::Dragon,
-                    "Mech" => api::nft::NftCardCategory::Mech,
-                    "Animal" => api::nft::NftCardCategory::Animal,
-                    "Plant" => api::nft::NftCardCategory::Plant,
-                    _ => api::nft::NftCardCategory::Neutral,
-                }
-            })
-            .collect();
-
-        let colors: HashSet<api::nft::NftCardColor> = config
-            .foil
-            .iter()
-            .flat_map(|foil| {
-                config.rarity.iter().map(move |rarity| {
-                    let foil = foil.as_str();
-                    let rarity = rarity.as_str();
-                    match (foil, rarity) {
-                        ("Regular", "Common") => NftCardColor::White,
-                        ("Regular", "Rare") => NftCardColor::Blue,
-                        ("Regular", "Epic") => NftCardColor::Purple,
-                        ("Regular", "Legendary") => NftCardColor::Orange,
-                        ("Gold", "Common") => NftCardColor::WhiteGold,
-                        ("Gold", "Rare") => NftCardColor::BlueGold,
-                        ("Gold", "Epic") => NftCardColor::PurpleGold,
-                        ("Gold", "Legendary") => NftCardColor::OrangeGold,
-                        _ => NftCardColor::White,
-                    }
-                })
-            })
-            .collect();
+        let (categories, colors) = check::card_filters(&config)?;
+        let cards = filter_cards(Some(categories), Some(colors))
+            .ok_or_else(|| anyhow::anyhow!("卡牌数据未初始化"))?;
 
-        let cards = filter_cards(Some(categories), Some(colors)).unwrap();
+        let params = StrategyParams {
+            gold_ratio: config.gold.ratio,
+            gold_rarity_ratio: config.gold.rarity_ratio.clone(),
+            ..Default::default()
+        };
+        let seller = Seller::new(config.sell.clone());
+        if let Some(state) = StoreState::load(&config.state_path)? {
+            tracing::info!(
+                "[守护]: 从{}恢复待挂单{}个, 挂单{}个, 下单记录{}条",
+                config.state_path,
+                state.holdings.len(),
+                state.listings.len(),
+                state.orders.len()
+            );
+            seller.restore(state.holdings, state.listings);
+            tracker.restore(state.orders);
+        }
+        let ledger = Ledger::open(&config.ledger_path)?;
+        let notifier = Notifier::new(&config.notify);
 
         Ok(Self {
             api,
@@ -113,9 +256,396 @@ impl Store {
             config,
             cards_number: cards.len() as u32,
             discrete_list,
+            tracker,
+            seller,
+            ledger,
+            feed,
+            valuation: FairValueModel::new(),
+            deals_observed: Mutex::new(None),
+            budget_notified: Mutex::new(None),
+            notifier,
+            params: RwLock::new(params),
+            control: Control::default(),
         })
     }
 
+    /// Saves pending holdings, listings and tracked orders to `state_path`.
+    pub fn save_state(&self) -> Result<()> {
+        let state = StoreState {
+            holdings: self.seller.holdings(),
+            listings: self.seller.listings(),
+            orders: self.tracker.snapshot(),
+        };
+        state.save(&self.config.state_path)
+    }
+
+    /// Current strategy thresholds.
+    pub fn params(&self) -> StrategyParams {
+        self.params.read().unwrap().clone()
+    }
+
+    /// Changes strategy thresholds; takes effect from the next listing checked.
+    pub fn update_params(&self, update: &ParamsUpdate) -> Result<StrategyParams> {
+        let mut params = self.params.write().unwrap();
+        let mut updated = params.clone();
+        update.apply(&mut updated)?;
+        *params = updated.clone();
+        tracing::info!("[控制]: 更新策略阈值 {:?}", update);
+        Ok(updated)
+    }
+
+    /// Snapshot of the running scanner for the control API.
+    pub fn status(&self) -> Status {
+        let mut tasks: Vec<String> = self.config.daemon.schedules.keys().cloned().collect();
+        tasks.sort();
+        let trades = self.ledger.trades();
+        let recent_orders = trades.iter().rev().take(RECENT_ORDERS).cloned().collect();
+        Status {
+            paused: self.control.is_paused(),
+            tasks,
+            last_cycle: self.control.last_cycle(),
+            balances: self.control.balances(),
+            params: self.params(),
+            recent_orders,
+            open_listings: self.seller.listings().len(),
+        }
+    }
+
+    async fn query_balance(&self, price_unity: &str) -> Result<f64> {
+        let balances = self.api.query_user_balance().await?;
+        // 没有该币种的余额时报错，不能拿其他币种的余额来比较
+        let balance = balances
+            .iter()
+            .find(|b| b.price_unity == price_unity)
+            .map(|b| b.balance)
+            .ok_or_else(|| anyhow::anyhow!("账户没有{}余额", price_unity))?;
+        self.record_balance(price_unity, balance);
+        Ok(balance)
+    }
+
+    fn record_balance(&self, price_unity: &str, balance: f64) {
+        metrics::gauge!(telemetry::WALLET_BALANCE, "unit" => price_unity.to_string()).set(balance);
+        self.control.set_balance(price_unity, balance);
+    }
+
+    // 购买前再次查询挂单详情，确认价格、等级经验、有效期与余额
+    async fn check_listing(&self, card: &CardInformation) -> Result<Result<(), Rejection>> {
+        let detail = self
+            .api
+            .query_buy_nft_detail(card.sale_aggregator_number.clone())
+            .await?;
+        let balance = match detail.user_balance.balance {
+            Some(balance) => {
+                self.record_balance(&card.price_unity, balance);
+                balance
+            }
+            None => self.query_balance(&card.price_unity).await?,
+        };
+        let own_tokens = self
+            .seller
+            .listings()
+            .into_iter()
+            .map(|listing| listing.holding.token_id)
+            .collect();
+        Ok(verify_listing(
+            card,
+            &detail,
+            &own_tokens,
+            balance,
+            utils::timestamp(),
+        ))
+    }
+
+    /// Buys a single listing after re-checking it with queryBuyNftDetail,
+    /// making sure the same `sale_aggregator_number` is never ordered twice
+    /// and known-failed listings are skipped.
+    pub async fn buy_card(
+        &self,
+        strategy: Strategy,
+        card_id: u32,
+        card: &CardInformation,
+    ) -> Result<BuyOutcome> {
+        let number = &card.sale_aggregator_number;
+        if self.control.is_paused() {
+            tracing::info!("[交易]: 已暂停购买, 跳过{}", card.nft_name);
+            return Ok(BuyOutcome::Paused);
+        }
+        if self.control.is_stopping() {
+            tracing::info!("[交易]: 正在退出, 跳过{}", card.nft_name);
+            return Ok(BuyOutcome::Stopping);
+        }
+        let cost = card.sale_price.parse::<f64>().unwrap_or(0.0);
+        // 预算次日恢复，不记入 tracker
+        if let Some(budget) = self.config.daily_budget {
+            let today = start_of_today();
+            let spent = self.ledger.spent_since(today);
+            if spent + cost > budget {
+                let rejection = Rejection::OverBudget {
+                    spent,
+                    budget,
+                    price: cost,
+                };
+                tracing::warn!("[交易]: 跳过{}, {}", card.nft_name, rejection);
+                let mut notified = self.budget_notified.lock().unwrap();
+                if *notified != Some(today) {
+                    *notified = Some(today);
+                    self.notifier.notify(Event::DailyBudgetReached {
+                        spent,
+                        budget,
+                        price: cost,
+                    });
+                }
+                return Ok(BuyOutcome::Rejected(rejection));
+            }
+        }
+        if !self.tracker.try_begin(number) {
+            tracing::info!("[交易]: {}已处理过, 跳过", card.nft_name);
+            return Ok(BuyOutcome::Skipped);
+        }
+
+        let label = ("strategy", strategy.name());
+        metrics::counter!(telemetry::CANDIDATES, label.0 => label.1).increment(1);
+        match self.check_listing(card).await {
+            Ok(Ok(())) => {}
+            Ok(Err(rejection)) => {
+                tracing::warn!("[交易]: {}复核未通过, {}", card.nft_name, rejection);
+                match rejection {
+                    // 只与本账户有关的原因不影响其他账户购买该挂单
+                    Rejection::InsufficientBalance { .. } | Rejection::OwnListing => {
+                        self.tracker.release(number)
+                    }
+                    _ => self.tracker.fail(number),
+                }
+                if let Rejection::InsufficientBalance { balance, price } = rejection {
+                    self.notifier
+                        .notify(Event::BudgetExhausted { balance, price });
+                }
+                return Ok(BuyOutcome::Rejected(rejection));
+            }
+            Err(e) => {
+                // 复核请求本身失败（网络等），挂单未被拒绝，释放后下轮可再尝试
+                self.tracker.release(number);
+                self.notify_error(&e);
+                return Err(e);
+            }
+        }
+
+        metrics::counter!(telemetry::ORDERS_ATTEMPTED, label.0 => label.1).increment(1);
+        match self.api.buy_ntf_asset(number).await {
+            Ok(_) => {
+                self.tracker.succeed(number);
+                metrics::counter!(telemetry::ORDERS_SUCCEEDED, label.0 => label.1).increment(1);
+                metrics::histogram!(telemetry::ORDER_SPEND, label.0 => label.1).record(cost);
+                let trade = Trade {
+                    side: TradeSide::Buy,
+                    card_id,
+                    token_id: card.token_id.clone(),
+                    nft_name: card.nft_name.clone(),
+                    exp: card.accumulate_trait.value,
+                    price: cost,
+                    // 平台费与版权费从卖方成交额中扣除，买方按挂单价支付，没有额外费用
+                    fee: 0.0,
+                    timestamp: utils::timestamp(),
+                    strategy: strategy.name().to_string(),
+                };
+                if let Err(e) = self.ledger.record(trade) {
+                    tracing::error!("[账本]: 记录{}失败, {:?}", card.nft_name, e);
+                }
+                if self.config.sell.enabled {
+                    self.seller.add_holding(Holding {
+                        card_id,
+                        token_id: card.token_id.clone(),
+                        price_unity: card.price_unity.clone(),
+                        nft_name: card.nft_name.clone(),
+                        exp: card.accumulate_trait.value,
+                        cost,
+                        strategy: strategy.name().to_string(),
+                    });
+                }
+                self.notifier.notify(Event::PurchaseSucceeded {
+                    card_id,
+                    nft_name: card.nft_name.clone(),
+                    price: cost,
+                    strategy: strategy.name().to_string(),
+                });
+                Ok(BuyOutcome::Bought)
+            }
+            Err(e) => {
+                self.tracker.fail(number);
+                self.notifier.notify(Event::PurchaseFailed {
+                    card_id,
+                    nft_name: card.nft_name.clone(),
+                    price: cost,
+                    strategy: strategy.name().to_string(),
+                    reason: e.to_string(),
+                });
+                self.notify_error(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Sends a session expired notification if `err` came from an expired login.
+    pub fn notify_error(&self, err: &anyhow::Error) {
+        if api::is_session_expired(err) {
+            self.notifier.notify(Event::SessionExpired {
+                reason: err.to_string(),
+            });
+        }
+    }
+
+    /// Runs one scan of the given buy strategy.
+    pub async fn run_strategy(&self, strategy: Strategy) -> Result<()> {
+        let started = Instant::now();
+        let result = match strategy {
+            Strategy::GoldVersusRegular => self.buy_nft_strategy_1().await,
+            Strategy::FiveAvg => self.buy_nft_strategy_with_five_avg().await,
+            Strategy::NeutralFloor => self.buy_nft_strategy_full().await,
+            Strategy::CheapCards => self.custom_scan_full().await,
+            Strategy::FairValue => self.fair_value_scan().await,
+        };
+        metrics::histogram!(telemetry::SCAN_DURATION, "strategy" => strategy.name())
+            .record(started.elapsed().as_secs_f64());
+        result
+    }
+
+    /// Lists purchased cards and reprices expired listings when selling is enabled.
+    pub async fn sell_cycle(&self) -> Result<()> {
+        if !self.config.sell.enabled {
+            return Ok(());
+        }
+        let sold = self.seller.run_cycle(&self.api, &self.ledger).await?;
+        for trade in sold {
+            self.notifier.notify(Event::ListingSold {
+                card_id: trade.card_id,
+                nft_name: trade.nft_name,
+                price: trade.price,
+            });
+        }
+        Ok(())
+    }
+
+    /// Feeds recent market sales into the fair value model.
+    ///
+    /// Only sales of tokens already seen in a listing are used, since the
+    /// sale history does not carry the card EXP. The history is fetched at
+    /// most once every `DEALS_INTERVAL`; calls in between do nothing.
+    pub async fn observe_deals(&self) -> Result<()> {
+        {
+            let mut observed = self.deals_observed.lock().unwrap();
+            if observed.is_some_and(|at| at.elapsed() < DEALS_INTERVAL) {
+                return Ok(());
+            }
+            *observed = Some(Instant::now());
+        }
+        let history = self.api.query_analyze_sell_history(NftId::Cards).await?;
+        let observed = history
+            .iter()
+            .filter_map(|deal| {
+                let price = deal.sale_price.parse::<f64>().ok()?;
+                Some(
+                    self.valuation
+                        .observe_deal(&deal.token_id, price, deal.sale_time),
+                )
+            })
+            .filter(|observed| *observed)
+            .count();
+        tracing::debug!("[估值]: 记录{}条成交", observed);
+        Ok(())
+    }
+
+    /// Secondary market listing of every card, unfiltered.
+    pub async fn scan_all_cards(&self) -> Result<MarketSecondaryResponse> {
+        let discrete_list = vec![
+            api::filter::Discrete::filter_type(vec![]),
+            api::filter::Discrete::faction(vec![]),
+            api::filter::Discrete::rarity(vec![]),
+            api::filter::Discrete::foil(vec![]),
+            api::filter::Discrete::source(vec![]),
+        ];
+
+        let scan = self
+            .feed
+            .secondary(
+                1,
+                222,
+                api::nft::NftSortType::PriceDescending,
+                &discrete_list,
+            )
+            .await?;
+        self.notify_alerts(scan.alerts);
+        Ok(scan.resp)
+    }
+
+    /// Checks the price alerts against the whole market without buying.
+    pub async fn alert_cycle(&self) -> Result<()> {
+        self.scan_all_cards().await?;
+        Ok(())
+    }
+
+    // 价格提醒命中时记录日志并发送通知
+    fn notify_alerts(&self, alerts: Vec<Alert>) {
+        for alert in alerts {
+            let nft_name = NftCardId::get_name_by_value(alert.card_id, "cn")
+                .unwrap_or_else(|| alert.card_id.to_string());
+            tracing::info!("[提醒]: {}, {}", nft_name, alert);
+            self.notifier.notify(Event::PriceAlert {
+                card_id: alert.card_id,
+                nft_name,
+                condition: alert.to_string(),
+            });
+        }
+    }
+
+    /// Current floor price (Price/EXP) of every card on the secondary market.
+    pub async fn scan_floor_prices(&self) -> Result<HashMap<u32, f64>> {
+        let resp = self.scan_all_cards().await?;
+
+        Ok(resp
+            .list
+            .iter()
+            .filter_map(|card| {
+                let floor = card.floor_price.parse::<f64>().ok()?;
+                Some((card.secondary_id, floor))
+            })
+            .collect())
+    }
+
+    /// Prints realized and unrealized P&L per strategy from the ledger.
+    pub async fn report_pnl(&self) -> Result<()> {
+        let floors = self.scan_floor_prices().await?;
+        let report = self.ledger.report(&floors);
+
+        let mut table = Table::new();
+        table.set_header(vec![
+            "策略",
+            "买入",
+            "卖出",
+            "成本",
+            "已实现盈亏",
+            "未实现盈亏",
+            "收益率",
+        ]);
+        for pnl in &report.strategies {
+            table.add_row(vec![
+                pnl.strategy.clone(),
+                pnl.buys.to_string(),
+                pnl.sells.to_string(),
+                format!("{:.4}", pnl.cost),
+                format!("{:.4}", pnl.realized),
+                format!("{:.4}", pnl.unrealized),
+                format!("{:.2}%", pnl.return_rate() * 100.0),
+            ]);
+        }
+        println!("{table}");
+        println!(
+            "已实现盈亏: {:.4}, 未实现盈亏: {:.4}",
+            report.realized, report.unrealized
+        );
+
+        Ok(())
+    }
+
     pub async fn scan_cards_full_secondary(&self) -> Result<()> {
         let discrete_list = vec![
             api::filter::Discrete::filter_type(vec![]),
@@ -125,16 +655,17 @@ impl Store {
             api::filter::Discrete::source(vec![]),
         ];
 
-        let resp = self
-            .api
-            .query_market_secondary(
-                NftId::Cards,
+        let scan = self
+            .feed
+            .secondary(
                 1,
                 222,
                 api::nft::NftSortType::PriceDescending,
                 &discrete_list,
             )
             .await?;
+        self.notify_alerts(scan.alerts);
+        let resp = scan.resp;
 
         let mut table = Table::new();
         table.set_header(vec!["卡牌名称", "ID", "最低价格", "在售数量", "成交数量"]);
@@ -201,118 +732,9 @@ impl Store {
     }
 
     pub async fn buy_nft_strategy_1(&self) -> Result<()> {
-        // 扫描某种卡牌的金卡和普通卡
-        // 查看普通卡价格，最便宜5张的均价，扫描金卡价格，金卡的价格小于普卡均价的1.1倍，直接购买金卡
-
-        let animals = vec![
-            (NftCardId::WolfKing, NftCardId::WolfKingGold),
-            (NftCardId::MasterPanda, NftCardId::MasterPandaGold),
-            (NftCardId::LionKing, NftCardId::LionKingGold),
-            (NftCardId::FuriousTiger, NftCardId::FuriousTigerGold),
-            (NftCardId::CunningFox, NftCardId::CunningFoxGold),
-            (NftCardId::Frog, NftCardId::FrogGold),
-            (NftCardId::Snake, NftCardId::SnakeGold),
-            (NftCardId::AngryBull, NftCardId::AngryBullGold),
-            (NftCardId::TortoiseBro, NftCardId::TortoiseBroGold),
-            (NftCardId::CunningFox, NftCardId::CunningFoxGold),
-        ];
-        let zombies = vec![
-            (
-                NftCardId::CrazyDoctorZombie,
-                NftCardId::CrazyDoctorZombieGold,
-            ),
-            (NftCardId::SuperZombie, NftCardId::SuperZombieGold),
-            (NftCardId::PirateZombie, NftCardId::PirateZombieGold),
-            (NftCardId::PartyZombie, NftCardId::PartyZombieGold),
-            (NftCardId::BarrelZombie, NftCardId::BarrelZombieGold),
-            (NftCardId::PrinceZombie, NftCardId::PrinceZombieGold),
-            (NftCardId::StarZombie, NftCardId::StarZombieGold),
-            (NftCardId::ClownZombie, NftCardId::ClownZombieGold),
-            (NftCardId::BombZombie, NftCardId::BombZombieGold),
-            (
-                NftCardId::CheerleaderZombie,
-                NftCardId::CheerleaderZombieGold,
-            ),
-            (NftCardId::RoadblockZombie, NftCardId::RoadblockZombieGold),
-            (NftCardId::DancerZombie, NftCardId::DancerZombieGold),
-            (NftCardId::ZombieBride, NftCardId::ZombieBrideGold),
-        ];
-        let plants = vec![
-            (NftCardId::DurianCommander, NftCardId::DurianCommanderGold),
-            (NftCardId::Garlic, NftCardId::GarlicGold),
-            (NftCardId::Pumpkin, NftCardId::PumpkinGold),
-            (NftCardId::Cabbage, NftCardId::CabbageGold),
-            (NftCardId::Watermelon, NftCardId::WatermelonGold),
-            (NftCardId::GrandpaBroccoli, NftCardId::GrandpaBroccoliGold),
-            (NftCardId::MrTomato, NftCardId::MrTomatoGold),
-            (NftCardId::Avocado, NftCardId::AvocadoGold),
-            (NftCardId::Pumpkin, NftCardId::PumpkinGold),
-            (NftCardId::DurianAgent, NftCardId::DurianAgentGold),
-            (NftCardId::Pineapple, NftCardId::PineappleGold),
-            (NftCardId::RedChili, NftCardId::RedChiliGold),
-            (NftCardId::Cactus, NftCardId::CactusGold),
-            (NftCardId::Radish, NftCardId::RadishGold),
-        ];
-        let _neutrals = vec![
-            (
-                NftCardId::LegendaryMiracleEgg,
-                NftCardId::LegendaryMiracleEggGold,
-            ),
-            (NftCardId::EpicMiracleEgg, NftCardId::EpicMiracleEggGold),
-            (NftCardId::RiotShield, NftCardId::RiotShieldGold),
-            (NftCardId::Poison, NftCardId::PoisonGold),
-            (NftCardId::AmmoCrate, NftCardId::AmmoCrateGold),
-            (NftCardId::StarvingMimic, NftCardId::StarvingMimicGold),
-            (NftCardId::Rocket, NftCardId::RocketGold),
-            (NftCardId::Trumpet, NftCardId::TrumpetGold),
-            (NftCardId::IronHelmet, NftCardId::IronHelmetGold),
-        ];
-
-        let _dragons = vec![
-            (NftCardId::RadiantDragon, NftCardId::RadiantDragonGold),
-            (NftCardId::ThunderDragon, NftCardId::ThunderDragonGold),
-            (NftCardId::RadiantDragon, NftCardId::RadiantDragonGold),
-            (NftCardId::OceanDragon, NftCardId::OceanDragonGold),
-            (NftCardId::StormDragon, NftCardId::StormDragonGold),
-            (NftCardId::RockDragon, NftCardId::RockDragonGold),
-            (NftCardId::TerraDragon, NftCardId::TerraDragonGold),
-            (NftCardId::NatureDragon, NftCardId::NatureDragonGold),
-            (NftCardId::ShadowDragon, NftCardId::ShadowDragonGold),
-            (NftCardId::RockDragon, NftCardId::RockDragonGold),
-            (NftCardId::DeceptionDragon, NftCardId::DeceptionDragonGold),
-            (NftCardId::TerraDragon, NftCardId::TerraDragonGold),
-            (NftCardId::DeceptionDragon, NftCardId::DeceptionDragonGold),
-            (NftCardId::CrystalDragon, NftCardId::CrystalDragonGold),
-            (NftCardId::NatureDragon, NftCardId::NatureDragonGold),
-            (NftCardId::DragonEgg, NftCardId::DragonEggGold),
-            (NftCardId::FrostDragon, NftCardId::FrostDragonGold),
-            (NftCardId::SteelDragon, NftCardId::SteelDragonGold),
-            (NftCardId::InfernoDragon, NftCardId::InfernoDragonGold),
-            (NftCardId::ObsidianDragon, NftCardId::ObsidianDragonGold),
-        ];
-
-        let _mechs = vec![
-            (NftCardId::ChargeBot, NftCardId::ChargeBotGold),
-            (NftCardId::GuardianBot, NftCardId::GuardianBotGold),
-            // NftCardId::GT130,
-            // NftCardId::GT130Gold,
-            (NftCardId::DoggyBot, NftCardId::DoggyBotGold),
-            (NftCardId::AvengerD0, NftCardId::AvengerD0Gold),
-            (NftCardId::DeterrentBot, NftCardId::DeterrentBotGold),
-            (NftCardId::MechHatchery, NftCardId::MechHatcheryGold),
-            (NftCardId::DoggyBot, NftCardId::DoggyBotGold),
-            (NftCardId::MechHatchery, NftCardId::MechHatcheryGold),
-            (NftCardId::MechanicKnight, NftCardId::MechanicKnightGold),
-            (NftCardId::KittyBot, NftCardId::KittyBotGold),
-            (NftCardId::ScoutBot, NftCardId::ScoutBotGold),
-            (NftCardId::SupportBot, NftCardId::SupportBotGold),
-            (NftCardId::AlarmBot, NftCardId::AlarmBotGold),
-        ];
-
-        let card_pairs: HashMap<NftCardId, NftCardId> = vec![animals, zombies, plants]
-            .into_iter()
-            .flatten()
-            .collect();
+        // 扫描每种卡牌的金卡和普通卡
+        // 查看普通卡价格，最便宜5张的均价，扫描金卡价格，金卡的价格小于普卡均价的一定倍数（按稀有度配置），直接购买金卡
+        let card_pairs = strategy::gold_pairs();
 
         let mut stream = futures::stream::iter(card_pairs.into_iter())
             .map(|(key, value)| async move {
@@ -326,13 +748,11 @@ impl Store {
             })
             .buffered(10);
 
+        let mut spreads = Vec::new();
         while let Some(result) = stream.next().await {
             match result {
                 Ok((key, value, card, card_gold)) => {
-                    // 普卡卡片数量不得低于5张
-                    if card.list.len() >= CARD_NUMBER_GREATER {
-                        self.process_card_gold(key, value, card, card_gold).await?;
-                    }
+                    spreads.push(self.process_card_gold(key, value, card, card_gold).await?);
                 }
                 Err(e) => {
                     println!("{:?}", e);
@@ -340,6 +760,7 @@ impl Store {
             }
         }
 
+        print_gold_spreads(&mut spreads);
         Ok(())
     }
 
@@ -349,59 +770,35 @@ impl Store {
         value: NftCardId,
         card: MarketHomeResponse,
         card_gold: MarketHomeResponse,
-    ) -> Result<()> {
+    ) -> Result<strategy::GoldSpread> {
         tracing::info!("[分析]: {} -> {}", value.to_chinese(), key.to_chinese());
-        let sum: f64 = card
-            .list
-            .iter()
-            .take(CARD_TOP_N)
-            .filter_map(|ci| {
-                let price = ci
-                    .metadata_list
-                    .iter()
-                    .find(|meta| meta.name == "Price/EXP")
-                    .map(|meta| meta.value.parse::<f64>().unwrap())
-                    .unwrap_or_else(|| {
-                        ci.sale_price.parse::<f64>().unwrap() / ci.accumulate_trait.value as f64
-                    });
-                Some(price)
-            })
-            .sum();
-        let avg = sum / CARD_TOP_N as f64;
-
-        // 前3张金卡
-        let len = card_gold.list.len();
-        let take_count = std::cmp::min(len, CARD_GOLD_TOP_N);
-
-        let _buys = futures::stream::iter(card_gold.list.iter().take(take_count))
-            .then(|cg| async move {
-                if let Some(price) = cg
-                    .metadata_list
-                    .iter()
-                    .find(|meta| meta.name == "Price/EXP")
-                    .and_then(|meta| meta.value.parse::<f64>().ok())
-                {
-                    if price < avg * 1.1 {
-                        let name_gold = NftCardId::get_name_by_id(value, "cn");
-                        println!(
-                            "[{}]: Found cheap card. Price:{}, avg:{}",
-                            name_gold, &cg.sale_price, &avg
-                        );
-                        if let Ok(_) = self.api.buy_ntf_asset(&cg.sale_aggregator_number).await {
-                            println!("[{}]: buy success.", value.to_chinese());
-                            // tracing::info!(
-                            //     "[{}]: Found cheap card. Price:{}",
-                            //     name_gold,
-                            //     cg.sale_price
-                            // );
-                        }
-                    }
-                }
-            })
-            .collect::<Vec<_>>()
-            .await;
+        let (spread, candidates) =
+            strategy::gold_versus_regular(&self.params(), (key, value), &card, &card_gold);
+        if let Some(ratio) = spread.spread() {
+            tracing::info!(
+                "[分析]: {} 价差{:.3}, 阈值{:.3}",
+                value.to_chinese(),
+                ratio,
+                spread.ratio
+            );
+        }
 
-        Ok(())
+        for cg in candidates {
+            tracing::info!(
+                "[交易]: {}低于阈值, 价格{}, 普卡均价{}",
+                value.to_chinese(),
+                &cg.sale_price,
+                spread.regular_avg.unwrap_or_default()
+            );
+            if let Ok(BuyOutcome::Bought) = self
+                .buy_card(Strategy::GoldVersusRegular, value as u32, cg)
+                .await
+            {
+                tracing::info!("[交易]: 购买{}成功", value.to_chinese());
+            }
+        }
+
+        Ok(spread)
     }
 
     pub async fn buy_nft_strategy_with_five_avg(&self) -> Result<()> {
@@ -418,55 +815,30 @@ impl Store {
         let neutrals = api::nft::filter_cards_id_only(Some(categories), None)?;
 
         let mut stream = futures::stream::iter(neutrals.into_iter())
-            .map(|neutral| {
+            .map(|neutral| async move {
                 println!("[扫描]: {}", neutral.to_chinese());
-                self.scan_card_home(neutral as u32)
+                let cards = self.scan_card_home(neutral as u32).await?;
+                Ok::<_, anyhow::Error>((neutral, cards))
             })
             .buffered(10);
 
         while let Some(result) = stream.next().await {
             match result {
-                Ok(cards) => {
-                    let sum: f64 = cards
-                        .list
-                        .iter()
-                        .take(5)
-                        .filter_map(|ci| {
-                            let price = ci
-                                .metadata_list
-                                .iter()
-                                .find(|meta| meta.name == "Price/EXP")
-                                .map(|meta| meta.value.parse::<f64>().unwrap())
-                                .unwrap_or_else(|| {
-                                    ci.sale_price.parse::<f64>().unwrap()
-                                        / ci.accumulate_trait.value as f64
-                                });
-                            Some(price)
-                        })
-                        .sum();
-
-                    let avg = sum / CARD_TOP_N as f64;
-                    if let Some(first_card) = cards.list.first() {
-                        let price = first_card
-                            .metadata_list
-                            .iter()
-                            .find(|meta| meta.name == "Price/EXP")
-                            .map(|meta| meta.value.parse::<f64>().unwrap())
-                            .unwrap_or_else(|| {
-                                first_card.sale_price.parse::<f64>().unwrap()
-                                    / first_card.accumulate_trait.value as f64
-                            });
-                        if price <= avg * 0.5 {
-                            if let Ok(_) = self
-                                .api
-                                .buy_ntf_asset(&first_card.sale_aggregator_number)
-                                .await
-                            {
-                                println!(
-                                    "[{}]: buy success. 购买价格:{}, 均价是:{}",
-                                    &first_card.nft_name, &first_card.sale_price, &avg
-                                );
-                            }
+                Ok((neutral, cards)) => {
+                    let found = strategy::five_avg(&self.params(), &cards, |ci| {
+                        self.tracker.contains(&ci.sale_aggregator_number)
+                    });
+                    if let Some((first_card, avg)) = found {
+                        if let Ok(BuyOutcome::Bought) = self
+                            .buy_card(Strategy::FiveAvg, neutral as u32, first_card)
+                            .await
+                        {
+                            tracing::info!(
+                                "[交易]: 购买{}成功, 价格{}, 均价{}",
+                                &first_card.nft_name,
+                                &first_card.sale_price,
+                                &avg
+                            );
                         }
                     }
                 }
@@ -512,31 +884,25 @@ impl Store {
         while let Some(result) = stream.next().await {
             match result {
                 Ok((cards, neutral)) => {
-                    if let Some(first_card) = cards.list.first() {
-                        let unit_price = first_card
-                            .metadata_list
-                            .iter()
-                            .find(|meta| meta.name == "Price/EXP")
-                            .map(|meta| meta.value.parse::<f64>().unwrap())
-                            .unwrap_or_else(|| {
-                                first_card.sale_price.parse::<f64>().unwrap()
-                                    / first_card.accumulate_trait.value as f64
-                            });
-
-                        if let Ok(floor_price) = neutral.floor_price.parse::<f64>() {
-                            if unit_price <= floor_price {
-                                tracing::info!("[交易]: 准备购买{}", first_card.nft_name);
-                                if let Ok(_) = self
-                                    .api
-                                    .buy_ntf_asset(&first_card.sale_aggregator_number)
-                                    .await
-                                {
+                    if let Ok(floor_price) = neutral.floor_price.parse::<f64>() {
+                        let found = strategy::floor_candidate(floor_price, &cards, None, |ci| {
+                            self.tracker.contains(&ci.sale_aggregator_number)
+                        });
+                        if let Some(first_card) = found {
+                            tracing::info!("[交易]: 准备购买{}", first_card.nft_name);
+                            match self
+                                .buy_card(Strategy::NeutralFloor, neutral.secondary_id, first_card)
+                                .await
+                            {
+                                Ok(BuyOutcome::Bought) => {
                                     tracing::info!(
                                         "[交易]: 购买{}成功, 价格为{}",
                                         first_card.nft_name,
                                         first_card.sale_price
                                     );
-                                } else {
+                                }
+                                Ok(_) => {}
+                                Err(_) => {
                                     tracing::error!("[交易]: 购买{}失败", first_card.nft_name);
                                 }
                             }
@@ -558,34 +924,18 @@ impl Store {
     ) -> Result<MarketSecondaryResponse> {
         let page = 1;
         let sort_type = NftSortType::PriceAscending;
-        let resp = if let Some(list) = discrete_list {
-            self.api
-                .query_market_secondary(NftId::Cards, page, self.cards_number, sort_type, &list)
-                .await?
-        } else {
-            self.api
-                .query_market_secondary(
-                    NftId::Cards,
-                    page,
-                    self.cards_number,
-                    sort_type,
-                    &self.discrete_list,
-                )
-                .await?
-        };
-        Ok(resp)
+        let list = discrete_list.as_ref().unwrap_or(&self.discrete_list);
+        let scan = self
+            .feed
+            .secondary(page, self.cards_number, sort_type, list)
+            .await?;
+        self.notify_alerts(scan.alerts);
+        Ok(scan.resp)
     }
 
     pub async fn scan_card_home(&self, card_id: u32) -> Result<MarketHomeResponse> {
-        let resp = self
-            .api
-            .query_market_home(
-                NftId::Cards,
-                card_id,
-                1,
-                api::market_home::MarketHomeSortType::PriceExpAscending,
-            )
-            .await?;
+        let resp = self.feed.home(card_id).await?;
+        self.valuation.observe_listings(card_id, &resp);
 
         Ok(resp)
     }
@@ -636,58 +986,32 @@ impl Store {
         while let Some(result) = stream.next().await {
             match result {
                 Ok((cards, cheap_card)) => {
-                    if let Some(first_card) = cards.list.first() {
-                        if first_card.accumulate_trait.value > 3 {
-                            tracing::info!("卡牌等级大于{}", first_card.accumulate_trait.value);
-                            continue;
-                        }
-                        // 如果卡片，有含有等级的卡片，则计算卡片实际的单价，即总价/卡片经验
-                        let unit_price = first_card
-                            .metadata_list
-                            .iter()
-                            .find(|meta| meta.name == "Price/EXP")
-                            .map(|meta| meta.value.parse::<f64>().unwrap())
-                            .unwrap_or_else(|| {
-                                first_card.sale_price.parse::<f64>().unwrap()
-                                    / first_card.accumulate_trait.value as f64
-                            });
-
-                        let exp = first_card.accumulate_trait.value as f64;
-
-                        // 使用了Buffer容器，可能会导致，同一张卡片，被扫描几次后统一处理。
-                        // 所以在这里，对卡片价格做一个二次检测。
-                        if let Ok(floor_price) = cheap_card.floor_price.parse::<f64>() {
-                            if unit_price <= floor_price {
-                                tracing::info!("[交易]: 准备购买{}", first_card.nft_name);
-                                // if let Ok(_) = self
-                                //     .api
-                                //     .buy_ntf_asset(&first_card.sale_aggregator_number)
-                                //     .await
-                                // {
-                                //     tracing::info!(
-                                //         "[交易]: 购买{}成功, 价格为{}",
-                                //         first_card.nft_name,
-                                //         first_card.sale_price
-                                //     );
-                                // } else {
-                                //     tracing::error!("[交易]: 购买{}失败", first_card.nft_name);
-                                // }
-                                let resp = self
-                                    .api
-                                    .buy_ntf_asset(&first_card.sale_aggregator_number)
-                                    .await;
-                                match resp {
-                                    Ok(_) => {
-                                        tracing::info!(
-                                            "[交易]: 购买{}成功, 价格为{}",
-                                            first_card.nft_name,
-                                            first_card.sale_price
-                                        );
-                                    }
-                                    Err(e) => {
-                                        tracing::error!("[交易]: 购买{}失败", first_card.nft_name);
-                                        tracing::error!("{:?}", e);
-                                    }
+                    // 使用了Buffer容器，可能会导致，同一张卡片，被扫描几次后统一处理。
+                    // 所以在这里，对卡片价格做一个二次检测，同一挂单由 tracker 保证只买一次。
+                    if let Ok(floor_price) = cheap_card.floor_price.parse::<f64>() {
+                        let found = strategy::floor_candidate(
+                            floor_price,
+                            &cards,
+                            Some(self.params().max_exp),
+                            |ci| self.tracker.contains(&ci.sale_aggregator_number),
+                        );
+                        if let Some(first_card) = found {
+                            tracing::info!("[交易]: 准备购买{}", first_card.nft_name);
+                            let resp = self
+                                .buy_card(Strategy::CheapCards, cheap_card.secondary_id, first_card)
+                                .await;
+                            match resp {
+                                Ok(BuyOutcome::Bought) => {
+                                    tracing::info!(
+                                        "[交易]: 购买{}成功, 价格为{}",
+                                        first_card.nft_name,
+                                        first_card.sale_price
+                                    );
+                                }
+                                Ok(_) => {}
+                                Err(e) => {
+                                    tracing::error!("[交易]: 购买{}失败", first_card.nft_name);
+                                    tracing::error!("{:?}", e);
                                 }
                             }
                         }
@@ -717,4 +1041,146 @@ impl Store {
         self.buy_cheap_cards(cheap_cards).await?;
         Ok(())
     }
+
+    /// Scans every configured card and buys the first listing priced at least
+    /// `fair_value_margin` below its modelled fair value. Unlike
+    /// [`Store::custom_scan_full`] it ignores the flat floor in `neutral.csv`,
+    /// so leveled cards priced above that floor can still be bought.
+    pub async fn fair_value_scan(&self) -> Result<()> {
+        tracing::info!("[扫描]: start...");
+        if let Err(e) = self.observe_deals().await {
+            tracing::error!("[估值]: 获取成交记录失败, {:?}", e);
+        }
+        let cards = self.scan_category_cards(None).await?;
+
+        let mut stream = futures::stream::iter(cards.list)
+            .map(|card| async move {
+                let home = self.scan_card_home(card.secondary_id).await?;
+                Ok::<_, anyhow::Error>((card.secondary_id, home))
+            })
+            .buffered(3);
+
+        while let Some(result) = stream.next().await {
+            let (card_id, home) = match result {
+                Ok(scanned) => scanned,
+                Err(e) => {
+                    tracing::error!("[扫描]: {:?}", e);
+                    continue;
+                }
+            };
+            let found =
+                self.valuation
+                    .undervalued(card_id, &home, self.params().fair_value_margin, |ci| {
+                        self.tracker.contains(&ci.sale_aggregator_number)
+                    });
+            let Some((first_card, fair)) = found else {
+                continue;
+            };
+            tracing::info!(
+                "[扫描]: {}低于公允价值{:.4}, 价格为{}",
+                first_card.nft_name,
+                fair,
+                first_card.sale_price
+            );
+            match self
+                .buy_card(Strategy::FairValue, card_id, first_card)
+                .await
+            {
+                Ok(BuyOutcome::Bought) => {
+                    tracing::info!(
+                        "[交易]: 购买{}成功, 价格为{}",
+                        first_card.nft_name,
+                        first_card.sale_price
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("[交易]: 购买{}失败", first_card.nft_name);
+                    tracing::error!("{:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // 不读取 neutral.csv、账本与状态文件写到临时目录的 Store，不发出网络请求的路径可用于测试
+    pub(crate) fn test_store(name: &str, configure: impl FnOnce(&mut utils::Config)) -> Store {
+        api::nft::init_nft_card_map();
+        let dir = std::env::temp_dir().join(format!("store-{}-{}", name, utils::timestamp()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+        let mut config = utils::Config::from_sources(
+            include_str!("../../config.example.toml"),
+            std::iter::empty(),
+        )
+        .unwrap();
+        config.ledger_path = path("ledger.csv");
+        config.state_path = path("state.json");
+        config.notify.sinks = vec![];
+        config.daemon.quiet_hours = vec![];
+        configure(&mut config);
+        let api = Arc::new(CardsAhoyApi::new(&config).unwrap());
+        let feed = Store::market_feed(&config).unwrap();
+        Store::build(config, api, feed, Arc::default(), HashMap::new()).unwrap()
+    }
+
+    pub(crate) fn remove_store(store: &Store) {
+        let dir = std::path::Path::new(&store.config.state_path).parent();
+        std::fs::remove_dir_all(dir.unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_daily_budget_notified_once() {
+        let inbox = std::env::temp_dir().join(format!("budget-{}.jsonl", utils::timestamp()));
+        let store = test_store("budget", |config| {
+            config.daily_budget = Some(0.5);
+            config.notify = utils::NotifyConfig {
+                sinks: vec![utils::NotifySink::Inbox {
+                    path: inbox.to_string_lossy().into(),
+                    events: vec![],
+                }],
+                rate_limit_secs: 0,
+            };
+        });
+        for number in ["SA1", "SA2"] {
+            let card = crate::strategy::tests::listing(number, 1.0, 1);
+            let outcome = store.buy_card(Strategy::CheapCards, 320, &card).await;
+            assert!(matches!(
+                outcome.unwrap(),
+                BuyOutcome::Rejected(Rejection::OverBudget { .. })
+            ));
+        }
+        // 通知按顺序发送，收到最后这条时前面的都已写入
+        store.notifier.notify(Event::SessionExpired {
+            reason: "done".into(),
+        });
+        let delivered = async {
+            loop {
+                let content = std::fs::read_to_string(&inbox).unwrap_or_default();
+                if content.contains("session_expired") {
+                    break content;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        let content = tokio::time::timeout(Duration::from_secs(5), delivered)
+            .await
+            .unwrap();
+        std::fs::remove_file(&inbox).unwrap();
+        remove_store(&store);
+
+        let budget: Vec<&str> = content
+            .lines()
+            .filter(|line| line.contains("budget_exhausted"))
+            .collect();
+        assert_eq!(budget.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(budget[0]).unwrap();
+        assert_eq!(json["budget"], 0.5);
+        assert_eq!(json["price"], 1.0);
+    }
 }
@@ -0,0 +1,338 @@
+use cardahoy_api::{
+    market_home::{CardInformation, MarketHomeResponse},
+    nft::{get_nft_card_color, NftCardId},
+};
+use serde::Serialize;
+use std::{collections::HashMap, str::FromStr};
+
+use crate::stats;
+
+/// The buy strategy that triggered an order, recorded in the ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    // 金卡 Price/EXP 低于普卡前5均价的1.1倍
+    GoldVersusRegular,
+    // 第一张低于前5均价的50%
+    FiveAvg,
+    // 中立普通卡底价低于价格表
+    NeutralFloor,
+    // 按配置筛选，底价低于价格表
+    CheapCards,
+    // 挂单价低于按经验估算的公允价值
+    FairValue,
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::GoldVersusRegular => "gold_versus_regular",
+            Strategy::FiveAvg => "five_avg",
+            Strategy::NeutralFloor => "neutral_floor",
+            Strategy::CheapCards => "cheap_cards",
+            Strategy::FairValue => "fair_value",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|s| s.name() == name)
+    }
+
+    pub fn all() -> Vec<Strategy> {
+        vec![
+            Strategy::GoldVersusRegular,
+            Strategy::FiveAvg,
+            Strategy::NeutralFloor,
+            Strategy::CheapCards,
+            Strategy::FairValue,
+        ]
+    }
+}
+
+/// Thresholds shared by the live strategies and the backtester.
+#[derive(Debug, Clone, Serialize)]
+pub struct StrategyParams {
+    // 计算均价时取前几张
+    pub top_n: usize,
+    // 金卡只看前几张
+    pub gold_top_n: usize,
+    // 普卡在售数量不得低于此值
+    pub min_listings: usize,
+    // 金卡 Price/EXP 低于普卡均价的倍数
+    pub gold_ratio: f64,
+    // 按稀有度覆盖 gold_ratio
+    pub gold_rarity_ratio: HashMap<String, f64>,
+    // 第一张低于前几张均价的比例
+    pub five_avg_ratio: f64,
+    // 底价策略可接受的最大卡牌经验
+    pub max_exp: u32,
+    // 挂单价需低于公允价值的比例
+    pub fair_value_margin: f64,
+}
+
+impl Default for StrategyParams {
+    fn default() -> Self {
+        Self {
+            top_n: 5,
+            gold_top_n: 3,
+            min_listings: 10,
+            gold_ratio: 1.1,
+            gold_rarity_ratio: HashMap::new(),
+            five_avg_ratio: 0.5,
+            max_exp: 3,
+            fair_value_margin: 0.15,
+        }
+    }
+}
+
+impl StrategyParams {
+    /// The gold ratio for a rarity, falling back to `gold_ratio`.
+    pub fn gold_ratio_for(&self, rarity: &str) -> f64 {
+        self.gold_rarity_ratio
+            .get(rarity)
+            .copied()
+            .unwrap_or(self.gold_ratio)
+    }
+}
+
+/// The "Price/EXP" value from the listing metadata, if present.
+pub fn price_per_exp(ci: &CardInformation) -> Option<f64> {
+    ci.metadata_list
+        .iter()
+        .find(|meta| meta.name == "Price/EXP")
+        .and_then(|meta| meta.value.parse::<f64>().ok())
+}
+
+/// 如果卡片，有含有等级的卡片，则计算卡片实际的单价，即总价/卡片经验
+pub fn unit_price(ci: &CardInformation) -> f64 {
+    price_per_exp(ci).unwrap_or_else(|| {
+        ci.sale_price.parse::<f64>().unwrap_or(f64::NAN) / ci.accumulate_trait.value.max(1) as f64
+    })
+}
+
+/// Average unit price of the first `n` listings, ignoring outliers.
+///
+/// Divides by the number of listings actually used, so a short book does
+/// not drag the average down. `None` if there are no usable prices.
+pub fn top_n_avg(list: &[CardInformation], n: usize) -> Option<f64> {
+    let prices: Vec<f64> = list.iter().take(n).map(unit_price).collect();
+    stats::mean(&stats::without_outliers(&prices, stats::OUTLIER_THRESHOLD))
+}
+
+/// The (regular, gold) pair `card_id` belongs to, matched by the " (Gold)"
+/// name suffix.
+pub fn gold_pair(card_id: u32) -> Option<(NftCardId, NftCardId)> {
+    let card = NftCardId::from_id(card_id)?;
+    let name = card.to_string();
+    match name.strip_suffix(" (Gold)") {
+        Some(regular) => Some((NftCardId::from_str(regular).ok()?, card)),
+        None => Some((card, NftCardId::from_str(&format!("{} (Gold)", name)).ok()?)),
+    }
+}
+
+/// Every (regular, gold) pair in the card catalog.
+pub fn gold_pairs() -> Vec<(NftCardId, NftCardId)> {
+    NftCardId::to_vec()
+        .into_iter()
+        .filter(|card| !get_nft_card_color(*card).is_gold())
+        .filter_map(|card| gold_pair(card as u32))
+        .collect()
+}
+
+/// Price gap between a gold variant and its regular card, in Price/EXP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldSpread {
+    pub regular: NftCardId,
+    pub gold: NftCardId,
+    pub rarity: &'static str,
+    // 普卡前N张均价
+    pub regular_avg: Option<f64>,
+    // 金卡最低 Price/EXP
+    pub gold_floor: Option<f64>,
+    // 该稀有度的买入倍数
+    pub ratio: f64,
+}
+
+impl GoldSpread {
+    /// Gold floor over the regular average. Gold is bought below `ratio`.
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.gold_floor? / self.regular_avg?)
+    }
+}
+
+/// Compares a gold variant to its regular card. Returns the spread and the
+/// gold listings whose Price/EXP is below the rarity ratio times the regular
+/// top-N average.
+pub fn gold_versus_regular<'a>(
+    params: &StrategyParams,
+    (regular_id, gold_id): (NftCardId, NftCardId),
+    regular: &MarketHomeResponse,
+    gold: &'a MarketHomeResponse,
+) -> (GoldSpread, Vec<&'a CardInformation>) {
+    let rarity = get_nft_card_color(gold_id).rarity();
+    let spread = GoldSpread {
+        regular: regular_id,
+        gold: gold_id,
+        rarity,
+        regular_avg: top_n_avg(&regular.list, params.top_n),
+        gold_floor: gold.list.iter().filter_map(price_per_exp).reduce(f64::min),
+        ratio: params.gold_ratio_for(rarity),
+    };
+
+    // 普卡卡片数量过少时均价不可信
+    let Some(avg) = spread.regular_avg else {
+        return (spread, vec![]);
+    };
+    if regular.list.len() < params.min_listings {
+        return (spread, vec![]);
+    }
+    let candidates = gold
+        .list
+        .iter()
+        .take(params.gold_top_n)
+        .filter(|cg| price_per_exp(cg).is_some_and(|price| price < avg * spread.ratio))
+        .collect();
+    (spread, candidates)
+}
+
+/// The first listing not skipped by `skip`, if it is at or below
+/// `five_avg_ratio` times the top-N average. Returns the listing and average.
+pub fn five_avg<'a>(
+    params: &StrategyParams,
+    home: &'a MarketHomeResponse,
+    skip: impl Fn(&CardInformation) -> bool,
+) -> Option<(&'a CardInformation, f64)> {
+    let avg = top_n_avg(&home.list, params.top_n)?;
+    let first = home.list.iter().find(|ci| !skip(ci))?;
+    if unit_price(first) <= avg * params.five_avg_ratio {
+        Some((first, avg))
+    } else {
+        None
+    }
+}
+
+/// The first listing not skipped by `skip`, if its unit price is at or below
+/// the secondary market `floor`. Listings above `max_exp` are refused.
+pub fn floor_candidate(
+    floor: f64,
+    home: &MarketHomeResponse,
+    max_exp: Option<u32>,
+    skip: impl Fn(&CardInformation) -> bool,
+) -> Option<&CardInformation> {
+    let first = home.list.iter().find(|ci| !skip(ci))?;
+    if let Some(max_exp) = max_exp {
+        if first.accumulate_trait.value > max_exp {
+            tracing::info!("卡牌等级大于{}", first.accumulate_trait.value);
+            return None;
+        }
+    }
+    if unit_price(first) <= floor {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    pub(crate) fn listing(number: &str, price: f64, exp: u32) -> CardInformation {
+        serde_json::from_value(json!({
+            "chainNftId": 12,
+            "amount": 1,
+            "priceUnity": "USDT",
+            "priorityTrait1": "1",
+            "accumulateTrait": { "name": "EXP", "value": exp },
+            "nftType": 0,
+            "image": "",
+            "salePrice": format!("{}", price * exp as f64),
+            "tokenId": number,
+            "nftName": format!("Axe #{}", number),
+            "priorityTrait2": format!("EXP:{}", exp),
+            "saleAggregatorNumber": number,
+            "metadataList": [{ "name": "Price/EXP", "value": format!("{}", price) }]
+        }))
+        .unwrap()
+    }
+
+    pub(crate) fn home(prices: &[f64]) -> MarketHomeResponse {
+        MarketHomeResponse {
+            list: prices
+                .iter()
+                .enumerate()
+                .map(|(i, price)| listing(&i.to_string(), *price, 1))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_five_avg() {
+        let params = StrategyParams::default();
+        let market = home(&[0.4, 1.0, 1.0, 1.0, 1.0]);
+        let (first, avg) = five_avg(&params, &market, |_| false).unwrap();
+        assert_eq!(first.sale_aggregator_number, "0");
+        // 第一张是离群值，不计入均价
+        assert!((avg - 1.0).abs() < 1e-9);
+
+        // 跳过已处理的第一张后，第二张不满足条件
+        assert!(five_avg(&params, &market, |ci| ci.sale_aggregator_number == "0").is_none());
+    }
+
+    #[test]
+    fn test_top_n_avg() {
+        // 不足 n 张时按实际数量计算
+        assert_eq!(top_n_avg(&home(&[1.0, 2.0]).list, 5), Some(1.5));
+        let avg = top_n_avg(&home(&[1.0, 1.1, 0.9, 1.0, 9.0]).list, 5).unwrap();
+        assert!((avg - 1.0).abs() < 1e-9);
+        assert_eq!(top_n_avg(&[], 5), None);
+    }
+
+    #[test]
+    fn test_gold_versus_regular() {
+        let mut params = StrategyParams::default();
+        let pair = (NftCardId::AmmoCrate, NftCardId::AmmoCrateGold);
+        let regular = home(&[1.0; 10]);
+        let gold = home(&[1.05, 1.2, 0.9, 0.5]);
+        let (spread, candidates) = gold_versus_regular(&params, pair, &regular, &gold);
+        assert!((spread.regular_avg.unwrap() - 1.0).abs() < 1e-9);
+        assert!((spread.spread().unwrap() - 0.5).abs() < 1e-9);
+        let numbers: Vec<&str> = candidates
+            .iter()
+            .map(|ci| ci.sale_aggregator_number.as_str())
+            .collect();
+        assert_eq!(numbers, vec!["0", "2"]);
+
+        // 按稀有度覆盖倍数
+        params
+            .gold_rarity_ratio
+            .insert(spread.rarity.to_string(), 0.95);
+        let (_, candidates) = gold_versus_regular(&params, pair, &regular, &gold);
+        assert_eq!(candidates.len(), 1);
+
+        // 普卡数量不足时只报告价差
+        let (spread, candidates) = gold_versus_regular(&params, pair, &home(&[1.0; 3]), &gold);
+        assert!(candidates.is_empty());
+        assert!(spread.spread().is_some());
+    }
+
+    #[test]
+    fn test_gold_pair() {
+        let pair = (NftCardId::AmmoCrate, NftCardId::AmmoCrateGold);
+        assert_eq!(gold_pair(NftCardId::AmmoCrate as u32), Some(pair));
+        assert_eq!(gold_pair(NftCardId::AmmoCrateGold as u32), Some(pair));
+        assert_eq!(gold_pair(0), None);
+        assert!(gold_pairs().contains(&pair));
+    }
+
+    #[test]
+    fn test_floor_candidate() {
+        let market = MarketHomeResponse {
+            list: vec![listing("a", 0.01, 5), listing("b", 0.01, 1)],
+        };
+        assert!(floor_candidate(0.02, &market, Some(3), |_| false).is_none());
+        let first = floor_candidate(0.02, &market, None, |_| false).unwrap();
+        assert_eq!(first.sale_aggregator_number, "a");
+        assert!(floor_candidate(0.005, &market, None, |_| false).is_none());
+    }
+}
@@ -0,0 +1,36 @@
+// 接口请求，在 api crate 中定义与记录
+pub use cardahoy_api::telemetry::{
+    API_HTML_PAGES, API_REQUESTS, API_REQUEST_DURATION, API_RETRIES,
+};
+// 扫描与交易
+pub const SCAN_DURATION: &str = "cardahoy_scan_duration_seconds";
+pub const DAEMON_CYCLE_DURATION: &str = "cardahoy_daemon_cycle_duration_seconds";
+pub const CANDIDATES: &str = "cardahoy_candidates_total";
+pub const ORDERS_ATTEMPTED: &str = "cardahoy_orders_attempted_total";
+pub const ORDERS_SUCCEEDED: &str = "cardahoy_orders_succeeded_total";
+pub const ORDER_SPEND: &str = "cardahoy_order_spend";
+pub const WALLET_BALANCE: &str = "cardahoy_wallet_balance";
+
+/// Registers help text for every metric the scanner records, so `/metrics`
+/// documents them before they are first recorded.
+pub fn describe_metrics() {
+    cardahoy_api::telemetry::describe_metrics();
+    metrics::describe_histogram!(
+        SCAN_DURATION,
+        metrics::Unit::Seconds,
+        "Duration of one buy strategy scan"
+    );
+    metrics::describe_histogram!(
+        DAEMON_CYCLE_DURATION,
+        metrics::Unit::Seconds,
+        "Duration of one daemon cycle"
+    );
+    metrics::describe_counter!(CANDIDATES, "Listings picked by a strategy for purchase");
+    metrics::describe_counter!(ORDERS_ATTEMPTED, "Orders sent after the listing re-check");
+    metrics::describe_counter!(ORDERS_SUCCEEDED, "Orders that completed");
+    metrics::describe_histogram!(
+        ORDER_SPEND,
+        "Price paid per order; the _sum is the total spend"
+    );
+    metrics::describe_gauge!(WALLET_BALANCE, "Last seen wallet balance by currency");
+}
@@ -0,0 +1,236 @@
+use cardahoy_utils as utils;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// 下单中的记录保留时间，超时后视为未知状态，允许再次尝试。
+const ATTEMPTED_TTL: Duration = Duration::from_secs(5 * 60);
+// 购买成功后，该挂单已经不存在，保留一天足够。
+const SUCCEEDED_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// 购买失败（被别人抢先、已下架等）后，一段时间内不再尝试。
+const FAILED_TTL: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Attempted,
+    Succeeded,
+    Failed,
+}
+
+/// A tracked listing as saved across restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackedOrder {
+    pub sale_aggregator_number: String,
+    pub status: OrderStatus,
+    // 最后更新时间，毫秒时间戳
+    pub updated_at: i64,
+}
+
+#[derive(Debug)]
+struct OrderEntry {
+    status: OrderStatus,
+    updated_at: Instant,
+}
+
+/// Remembers which listings (by `sale_aggregator_number`) have been attempted,
+/// bought or rejected, so that repeated scan cycles buy each listing at most once.
+#[derive(Debug)]
+pub struct OrderTracker {
+    orders: Mutex<HashMap<String, OrderEntry>>,
+    attempted_ttl: Duration,
+    succeeded_ttl: Duration,
+    failed_ttl: Duration,
+}
+
+impl Default for OrderTracker {
+    fn default() -> Self {
+        Self::new(ATTEMPTED_TTL, SUCCEEDED_TTL, FAILED_TTL)
+    }
+}
+
+impl OrderTracker {
+    pub fn new(attempted_ttl: Duration, succeeded_ttl: Duration, failed_ttl: Duration) -> Self {
+        Self {
+            orders: Mutex::new(HashMap::new()),
+            attempted_ttl,
+            succeeded_ttl,
+            failed_ttl,
+        }
+    }
+
+    fn ttl(&self, status: OrderStatus) -> Duration {
+        match status {
+            OrderStatus::Attempted => self.attempted_ttl,
+            OrderStatus::Succeeded => self.succeeded_ttl,
+            OrderStatus::Failed => self.failed_ttl,
+        }
+    }
+
+    fn purge(&self, orders: &mut HashMap<String, OrderEntry>) {
+        let now = Instant::now();
+        orders.retain(|_, entry| now.duration_since(entry.updated_at) < self.ttl(entry.status));
+    }
+
+    /// Returns the current status of a listing, ignoring expired entries.
+    pub fn status(&self, sale_aggregator_number: &str) -> Option<OrderStatus> {
+        let mut orders = self.orders.lock().unwrap();
+        self.purge(&mut orders);
+        orders.get(sale_aggregator_number).map(|entry| entry.status)
+    }
+
+    /// Whether the listing is already in flight, bought or known to fail.
+    pub fn contains(&self, sale_aggregator_number: &str) -> bool {
+        self.status(sale_aggregator_number).is_some()
+    }
+
+    /// Marks the listing as attempted. Returns `false` if it is already
+    /// tracked, in which case the caller must not place the order.
+    pub fn try_begin(&self, sale_aggregator_number: &str) -> bool {
+        let mut orders = self.orders.lock().unwrap();
+        self.purge(&mut orders);
+        if orders.contains_key(sale_aggregator_number) {
+            return false;
+        }
+        orders.insert(
+            sale_aggregator_number.to_string(),
+            OrderEntry {
+                status: OrderStatus::Attempted,
+                updated_at: Instant::now(),
+            },
+        );
+        true
+    }
+
+    pub fn succeed(&self, sale_aggregator_number: &str) {
+        self.update(sale_aggregator_number, OrderStatus::Succeeded);
+    }
+
+    pub fn fail(&self, sale_aggregator_number: &str) {
+        self.update(sale_aggregator_number, OrderStatus::Failed);
+    }
+
+    /// Forgets the listing, e.g. when the pre-buy check could not reach the
+    /// server, so the next cycle may try it again.
+    pub fn release(&self, sale_aggregator_number: &str) {
+        self.orders.lock().unwrap().remove(sale_aggregator_number);
+    }
+
+    /// The entries that have not expired yet, for saving on shutdown.
+    pub fn snapshot(&self) -> Vec<TrackedOrder> {
+        let mut orders = self.orders.lock().unwrap();
+        self.purge(&mut orders);
+        let now = utils::timestamp();
+        orders
+            .iter()
+            .map(|(number, entry)| TrackedOrder {
+                sale_aggregator_number: number.clone(),
+                status: entry.status,
+                updated_at: now - entry.updated_at.elapsed().as_millis() as i64,
+            })
+            .collect()
+    }
+
+    /// Reloads saved entries, keeping their age so they expire on schedule.
+    /// Entries already tracked in memory are left as they are.
+    pub fn restore(&self, saved: Vec<TrackedOrder>) {
+        let mut orders = self.orders.lock().unwrap();
+        let (now, now_ms) = (Instant::now(), utils::timestamp());
+        for order in saved {
+            let age = Duration::from_millis((now_ms - order.updated_at).max(0) as u64);
+            if age >= self.ttl(order.status) {
+                continue;
+            }
+            let Some(updated_at) = now.checked_sub(age) else {
+                continue;
+            };
+            orders
+                .entry(order.sale_aggregator_number)
+                .or_insert(OrderEntry {
+                    status: order.status,
+                    updated_at,
+                });
+        }
+    }
+
+    fn update(&self, sale_aggregator_number: &str, status: OrderStatus) {
+        let mut orders = self.orders.lock().unwrap();
+        orders.insert(
+            sale_aggregator_number.to_string(),
+            OrderEntry {
+                status,
+                updated_at: Instant::now(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_begin_once() {
+        let tracker = OrderTracker::default();
+        assert!(tracker.try_begin("1001"));
+        assert!(!tracker.try_begin("1001"));
+        assert_eq!(tracker.status("1001"), Some(OrderStatus::Attempted));
+        assert!(tracker.try_begin("1002"));
+    }
+
+    #[test]
+    fn test_failed_is_skipped() {
+        let tracker = OrderTracker::default();
+        assert!(tracker.try_begin("1001"));
+        tracker.fail("1001");
+        assert_eq!(tracker.status("1001"), Some(OrderStatus::Failed));
+        assert!(!tracker.try_begin("1001"));
+    }
+
+    #[test]
+    fn test_release() {
+        let tracker = OrderTracker::default();
+        assert!(tracker.try_begin("1001"));
+        tracker.release("1001");
+        assert!(!tracker.contains("1001"));
+        assert!(tracker.try_begin("1001"));
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let tracker = OrderTracker::default();
+        assert!(tracker.try_begin("1001"));
+        assert!(tracker.try_begin("1002"));
+        tracker.fail("1002");
+        let mut saved = tracker.snapshot();
+        // 超过失败保留时间的记录不再恢复
+        saved.push(TrackedOrder {
+            sale_aggregator_number: "1003".into(),
+            status: OrderStatus::Failed,
+            updated_at: utils::timestamp() - FAILED_TTL.as_millis() as i64,
+        });
+
+        let restored = OrderTracker::default();
+        restored.restore(saved);
+        assert_eq!(restored.status("1001"), Some(OrderStatus::Attempted));
+        assert_eq!(restored.status("1002"), Some(OrderStatus::Failed));
+        assert_eq!(restored.status("1003"), None);
+        assert!(!restored.try_begin("1001"));
+    }
+
+    #[test]
+    fn test_expired_entries_are_forgotten() {
+        let tracker = OrderTracker::new(Duration::ZERO, SUCCEEDED_TTL, Duration::ZERO);
+        assert!(tracker.try_begin("1001"));
+        assert_eq!(tracker.status("1001"), None);
+        assert!(tracker.try_begin("1001"));
+        tracker.fail("1001");
+        assert!(!tracker.contains("1001"));
+
+        assert!(tracker.try_begin("1002"));
+        tracker.succeed("1002");
+        assert!(!tracker.try_begin("1002"));
+    }
+}
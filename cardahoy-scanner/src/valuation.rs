@@ -0,0 +1,341 @@
+use cardahoy_api::market_home::{CardInformation, MarketHomeResponse};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::stats;
+
+// 成交比挂单更能反映真实价格
+pub const LISTING_WEIGHT: f64 = 1.0;
+pub const DEAL_WEIGHT: f64 = 2.0;
+// 样本过少时不给出估值
+pub const MIN_OBSERVATIONS: usize = 5;
+// 每张卡牌保留的最近成交数量
+const MAX_DEALS: usize = 50;
+// 最多记住的挂单卡牌数量，超出后丢弃最久未出现在挂单中的
+const MAX_TOKENS: usize = 20_000;
+// 只记录最新成交前这段时间内的成交，更早的去重记录一并丢弃，毫秒
+const DEAL_WINDOW: u64 = 7 * 24 * 60 * 60 * 1000;
+
+/// One observed price for a card with the given EXP.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+    pub exp: u32,
+    // 总价，不是 Price/EXP
+    pub price: f64,
+    pub weight: f64,
+}
+
+/// Fair price of a card as a linear function of its EXP.
+///
+/// Level is derived from EXP, so a per-EXP slope plus a fixed base captures
+/// leveled copies trading at a premium or discount to level-1 copies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CardValue {
+    pub base: f64,
+    pub per_exp: f64,
+}
+
+impl CardValue {
+    pub fn fair_price(&self, exp: u32) -> f64 {
+        (self.base + self.per_exp * exp.max(1) as f64).max(0.0)
+    }
+}
+
+/// Weighted least squares fit of price against EXP.
+///
+/// Falls back to a proportional model (weighted average Price/EXP, no base)
+/// when every observation has the same EXP or the fitted slope is not
+/// positive.
+pub fn fit(observations: &[Observation]) -> Option<CardValue> {
+    let total: f64 = observations.iter().map(|o| o.weight).sum();
+    if observations.is_empty() || total <= 0.0 {
+        return None;
+    }
+
+    let mean_exp = observations
+        .iter()
+        .map(|o| o.weight * o.exp.max(1) as f64)
+        .sum::<f64>()
+        / total;
+    let mean_price = observations.iter().map(|o| o.weight * o.price).sum::<f64>() / total;
+
+    let (mut cov, mut var) = (0.0, 0.0);
+    for o in observations {
+        let dx = o.exp.max(1) as f64 - mean_exp;
+        cov += o.weight * dx * (o.price - mean_price);
+        var += o.weight * dx * dx;
+    }
+
+    if var > f64::EPSILON {
+        let per_exp = cov / var;
+        if per_exp > 0.0 {
+            return Some(CardValue {
+                base: mean_price - per_exp * mean_exp,
+                per_exp,
+            });
+        }
+    }
+
+    let per_exp = observations
+        .iter()
+        .map(|o| o.weight * o.price / o.exp.max(1) as f64)
+        .sum::<f64>()
+        / total;
+    Some(CardValue { base: 0.0, per_exp })
+}
+
+#[derive(Default)]
+struct ModelState {
+    // 最近一次扫描到的挂单，按卡牌 id
+    listings: HashMap<u32, Vec<Observation>>,
+    deals: HashMap<u32, Vec<Observation>>,
+    // 成交记录只有 token_id，用挂单中见过的卡牌与经验补全
+    tokens: HashMap<String, SeenToken>,
+    // observe_listings 的调用次数，用来判断卡牌多久没出现在挂单中
+    generation: u64,
+    // 成交记录每次都会全量返回，按 token_id 与成交时间去重
+    seen_deals: HashSet<(String, u64)>,
+    // 见过的最新成交时间
+    latest_deal: u64,
+}
+
+struct SeenToken {
+    card_id: u32,
+    exp: u32,
+    generation: u64,
+}
+
+impl ModelState {
+    // 卡牌数量超出上限时，只保留最近出现过的 MAX_TOKENS 张
+    fn prune_tokens(&mut self) {
+        if self.tokens.len() <= MAX_TOKENS {
+            return;
+        }
+        let mut generations: Vec<u64> = self.tokens.values().map(|t| t.generation).collect();
+        generations.sort_unstable_by(|a, b| b.cmp(a));
+        let cutoff = generations[MAX_TOKENS - 1];
+        self.tokens.retain(|_, token| token.generation >= cutoff);
+    }
+
+    fn deal_cutoff(&self) -> u64 {
+        self.latest_deal.saturating_sub(DEAL_WINDOW)
+    }
+}
+
+/// Estimates per-card fair value from observed listings and deals.
+///
+/// Memory stays bounded: at most `MAX_TOKENS` listed tokens are remembered,
+/// and deals older than `DEAL_WINDOW` before the newest one are ignored and
+/// forgotten.
+#[derive(Default)]
+pub struct FairValueModel {
+    state: Mutex<ModelState>,
+}
+
+impl FairValueModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the listing observations of `card_id` with the current order book.
+    pub fn observe_listings(&self, card_id: u32, home: &MarketHomeResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        let generation = state.generation;
+        let mut listings = Vec::with_capacity(home.list.len());
+        for ci in &home.list {
+            let Ok(price) = ci.sale_price.parse::<f64>() else {
+                continue;
+            };
+            let exp = ci.accumulate_trait.value;
+            state.tokens.insert(
+                ci.token_id.clone(),
+                SeenToken {
+                    card_id,
+                    exp,
+                    generation,
+                },
+            );
+            listings.push(Observation {
+                exp,
+                price,
+                weight: LISTING_WEIGHT,
+            });
+        }
+        state.listings.insert(card_id, listings);
+        state.prune_tokens();
+    }
+
+    /// Records a completed sale. Returns false if the sale was already
+    /// recorded or is outside the deal window, or the token was never seen in
+    /// a listing since its card and EXP are then unknown.
+    pub fn observe_deal(&self, token_id: &str, price: f64, sale_time: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some((card_id, exp)) = state.tokens.get(token_id).map(|t| (t.card_id, t.exp)) else {
+            return false;
+        };
+        if sale_time < state.deal_cutoff()
+            || !state.seen_deals.insert((token_id.to_string(), sale_time))
+        {
+            return false;
+        }
+        if sale_time > state.latest_deal {
+            state.latest_deal = sale_time;
+            let cutoff = state.deal_cutoff();
+            state.seen_deals.retain(|(_, time)| *time >= cutoff);
+        }
+        let deals = state.deals.entry(card_id).or_default();
+        deals.push(Observation {
+            exp,
+            price,
+            weight: DEAL_WEIGHT,
+        });
+        if deals.len() > MAX_DEALS {
+            deals.remove(0);
+        }
+        true
+    }
+
+    pub fn value(&self, card_id: u32) -> Option<CardValue> {
+        let state = self.state.lock().unwrap();
+        let observations: Vec<Observation> = state
+            .listings
+            .get(&card_id)
+            .into_iter()
+            .chain(state.deals.get(&card_id))
+            .flatten()
+            .copied()
+            .collect();
+        // 剔除 Price/EXP 离群的挂单与成交
+        let units: Vec<f64> = observations
+            .iter()
+            .map(|o| o.price / o.exp.max(1) as f64)
+            .collect();
+        let observations: Vec<Observation> = observations
+            .into_iter()
+            .zip(stats::outliers(&units, stats::OUTLIER_THRESHOLD))
+            .filter(|(_, outlier)| !outlier)
+            .map(|(o, _)| o)
+            .collect();
+        if observations.len() < MIN_OBSERVATIONS {
+            return None;
+        }
+        fit(&observations)
+    }
+
+    pub fn fair_price(&self, card_id: u32, exp: u32) -> Option<f64> {
+        self.value(card_id).map(|value| value.fair_price(exp))
+    }
+
+    /// The first listing not skipped by `skip` priced at least `margin` below
+    /// its modelled fair value. Returns the listing and its fair price.
+    pub fn undervalued<'a>(
+        &self,
+        card_id: u32,
+        home: &'a MarketHomeResponse,
+        margin: f64,
+        skip: impl Fn(&CardInformation) -> bool,
+    ) -> Option<(&'a CardInformation, f64)> {
+        let value = self.value(card_id)?;
+        home.list.iter().filter(|ci| !skip(ci)).find_map(|ci| {
+            let price = ci.sale_price.parse::<f64>().ok()?;
+            let fair = value.fair_price(ci.accumulate_trait.value);
+            (price <= fair * (1.0 - margin)).then_some((ci, fair))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::tests::listing;
+
+    fn obs(exp: u32, price: f64) -> Observation {
+        Observation {
+            exp,
+            price,
+            weight: LISTING_WEIGHT,
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        // price = 0.5 + 1.0 * exp
+        let value = fit(&[obs(1, 1.5), obs(2, 2.5), obs(5, 5.5)]).unwrap();
+        assert!((value.base - 0.5).abs() < 1e-9);
+        assert!((value.per_exp - 1.0).abs() < 1e-9);
+        assert!((value.fair_price(3) - 3.5).abs() < 1e-9);
+
+        // 经验全部相同时按 Price/EXP 估值
+        let value = fit(&[obs(2, 2.0), obs(2, 4.0)]).unwrap();
+        assert_eq!(value.base, 0.0);
+        assert!((value.per_exp - 1.5).abs() < 1e-9);
+
+        assert!(fit(&[]).is_none());
+    }
+
+    #[test]
+    fn test_undervalued_leveled_card() {
+        let model = FairValueModel::new();
+        let home = MarketHomeResponse {
+            list: vec![
+                listing("a", 1.0, 1),
+                listing("b", 1.0, 1),
+                listing("c", 1.0, 2),
+                listing("d", 1.0, 3),
+                listing("e", 0.5, 5),
+                listing("f", 1.0, 4),
+            ],
+        };
+        model.observe_listings(320, &home);
+
+        let (ci, fair) = model.undervalued(320, &home, 0.25, |_| false).unwrap();
+        assert_eq!(ci.sale_aggregator_number, "e");
+        assert!(fair > 3.0);
+        assert!(model
+            .undervalued(320, &home, 0.25, |ci| ci.sale_aggregator_number == "e")
+            .is_none());
+
+        assert!(model.observe_deal("e", 5.0, 1));
+        assert!(!model.observe_deal("e", 5.0, 1));
+        assert!(!model.observe_deal("unknown", 5.0, 1));
+        assert!(model.fair_price(437, 1).is_none());
+    }
+
+    #[test]
+    fn test_prune() {
+        let model = FairValueModel::new();
+        let home = MarketHomeResponse {
+            list: vec![listing("a", 1.0, 1)],
+        };
+        model.observe_listings(320, &home);
+
+        // 窗口之外的旧成交不再记录，去重集合随最新成交滑动
+        assert!(model.observe_deal("a", 1.0, DEAL_WINDOW + 10));
+        assert!(!model.observe_deal("a", 1.0, 5));
+        assert!(model.observe_deal("a", 1.0, 3 * DEAL_WINDOW));
+        assert_eq!(model.state.lock().unwrap().seen_deals.len(), 1);
+
+        let mut state = model.state.lock().unwrap();
+        for i in 0..MAX_TOKENS as u64 + 10 {
+            state.generation += 1;
+            let generation = state.generation;
+            state.tokens.insert(
+                i.to_string(),
+                SeenToken {
+                    card_id: 320,
+                    exp: 1,
+                    generation,
+                },
+            );
+        }
+        state.prune_tokens();
+        assert_eq!(state.tokens.len(), MAX_TOKENS);
+        assert!(!state.tokens.contains_key("a"));
+        assert!(state
+            .tokens
+            .contains_key(&(MAX_TOKENS as u64 + 9).to_string()));
+    }
+}
@@ -0,0 +1,295 @@
+use cardahoy_api::{buy_nft_detail::BuyNftDetailResponse, market_home::CardInformation};
+use std::{collections::HashSet, fmt};
+
+/// Reason a candidate listing was rejected right before purchase.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    // 交易编码或卡牌 token_id 不一致，说明挂单已经换人或重新上架
+    ListingChanged,
+    // 挂单来自本账户（卖出模块挂出的卡牌），不能自买自卖
+    OwnListing,
+    PriceChanged { expected: f64, actual: f64 },
+    LevelChanged { expected: String, actual: String },
+    ExpChanged { expected: u32, actual: u32 },
+    // 详情中缺少复核所需的等级或经验，无法确认挂单未变
+    MissingDetail { field: &'static str },
+    Expired { end_time: u64 },
+    SoldOut,
+    InsufficientBalance { balance: f64, price: f64 },
+    // 超出账户每日预算
+    OverBudget { spent: f64, budget: f64, price: f64 },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::ListingChanged => write!(f, "listing changed"),
+            Rejection::OwnListing => write!(f, "listing is our own"),
+            Rejection::PriceChanged { expected, actual } => {
+                write!(f, "price changed: {} -> {}", expected, actual)
+            }
+            Rejection::LevelChanged { expected, actual } => {
+                write!(f, "level changed: {} -> {}", expected, actual)
+            }
+            Rejection::ExpChanged { expected, actual } => {
+                write!(f, "exp changed: {} -> {}", expected, actual)
+            }
+            Rejection::MissingDetail { field } => write!(f, "listing detail has no {}", field),
+            Rejection::Expired { end_time } => write!(f, "listing expired at {}", end_time),
+            Rejection::SoldOut => write!(f, "listing sold out"),
+            Rejection::InsufficientBalance { balance, price } => {
+                write!(f, "insufficient balance: {} < {}", balance, price)
+            }
+            Rejection::OverBudget {
+                spent,
+                budget,
+                price,
+            } => write!(
+                f,
+                "daily budget exceeded: {} spent of {}, price {}",
+                spent, budget, price
+            ),
+        }
+    }
+}
+
+fn parse_price(price: &str) -> f64 {
+    price.parse::<f64>().unwrap_or(f64::NAN)
+}
+
+/// Compares the listing seen on queryMarketHome with a fresh
+/// queryBuyNftDetail response.
+///
+/// The market home listing does not expose the seller or end time, so there
+/// is no earlier seller to compare against. A sale aggregator number belongs
+/// to a single listing by a single seller (relisting issues a new number), so
+/// matching the number and token id means the seller is unchanged. On top of
+/// that the listing must name a seller, and cards we listed ourselves
+/// (`own_tokens`) are never bought back. The end time is checked against
+/// `now` (milliseconds). A detail without the level or EXP is rejected
+/// rather than trusted.
+pub fn verify_listing(
+    card: &CardInformation,
+    detail: &BuyNftDetailResponse,
+    own_tokens: &HashSet<String>,
+    balance: f64,
+    now: i64,
+) -> Result<(), Rejection> {
+    let base_info = &detail.base_info;
+    if detail.sale_aggregator_number != card.sale_aggregator_number
+        || base_info.token_id != card.token_id
+        || base_info.owner.is_empty()
+    {
+        return Err(Rejection::ListingChanged);
+    }
+
+    if own_tokens.contains(&base_info.token_id) {
+        return Err(Rejection::OwnListing);
+    }
+
+    if base_info.amount == 0 {
+        return Err(Rejection::SoldOut);
+    }
+
+    if base_info.end_time != 0 && (base_info.end_time as i64) <= now {
+        return Err(Rejection::Expired {
+            end_time: base_info.end_time,
+        });
+    }
+
+    let expected = parse_price(&card.sale_price);
+    let actual = parse_price(&base_info.sale_price);
+    if (expected - actual).abs() > f64::EPSILON || actual.is_nan() {
+        return Err(Rejection::PriceChanged { expected, actual });
+    }
+
+    let Some(level) = &base_info.priority_level_trait else {
+        return Err(Rejection::MissingDetail { field: "level" });
+    };
+    if *level != card.priority_trait1 {
+        return Err(Rejection::LevelChanged {
+            expected: card.priority_trait1.clone(),
+            actual: level.clone(),
+        });
+    }
+
+    // 卡牌经验在 meta 信息中，trait_type 与 accumulate_trait 的 name 对应
+    let exp = &card.accumulate_trait;
+    let actual_exp = detail
+        .meta_data
+        .iter()
+        .find(|meta| meta.trait_type == exp.name)
+        .and_then(|meta| meta.value.parse::<u32>().ok())
+        .ok_or(Rejection::MissingDetail { field: "exp" })?;
+    if actual_exp != exp.value {
+        return Err(Rejection::ExpChanged {
+            expected: exp.value,
+            actual: actual_exp,
+        });
+    }
+
+    if balance < actual {
+        return Err(Rejection::InsufficientBalance {
+            balance,
+            price: actual,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn card() -> CardInformation {
+        serde_json::from_value(json!({
+            "chainNftId": 12,
+            "amount": 1,
+            "priceUnity": "USDT",
+            "priorityTrait1": "2",
+            "accumulateTrait": { "name": "EXP", "value": 3 },
+            "nftType": 0,
+            "image": "",
+            "salePrice": "0.0150",
+            "tokenId": "8842",
+            "nftName": "Axe #8842",
+            "priorityTrait2": "EXP:3",
+            "saleAggregatorNumber": "SA1001",
+            "metadataList": [{ "name": "Price/EXP", "value": "0.005" }]
+        }))
+        .unwrap()
+    }
+
+    fn detail(sale_price: &str, exp: &str, end_time: u64) -> BuyNftDetailResponse {
+        serde_json::from_value(json!({
+            "userBalance": { "balance": null, "priceUnity": "USDT" },
+            "coinId": 1,
+            "chainName": "BSC",
+            "contractInfo": {
+                "tokenStandard": "ERC721",
+                "copyrightFee": 0.0,
+                "platformFee": 0.05,
+                "contractAddress": "0x0",
+                "tokenId": "8842",
+                "chainName": "BSC"
+            },
+            "metaData": [{
+                "value": exp,
+                "displayColor": "",
+                "ifDisplayBold": false,
+                "traitType": "EXP"
+            }],
+            "ownerList": null,
+            "saleAggregatorNumber": "SA1001",
+            "chainNftId": 12,
+            "baseInfo": {
+                "amount": 1,
+                "owner": "seller",
+                "endTime": end_time,
+                "ownerProfileImageUrl": null,
+                "nftName": "Axe #8842",
+                "image": "",
+                "salePrice": sale_price,
+                "priceUnity": "USDT",
+                "tokenId": "8842",
+                "priorityLevelTrait": "2",
+                "desc": null
+            },
+            "chainId": 56
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_listing_ok() {
+        let result = verify_listing(
+            &card(),
+            &detail("0.015", "3", 2_000),
+            &HashSet::new(),
+            1.0,
+            1_000,
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_verify_listing_price_changed() {
+        let result = verify_listing(
+            &card(),
+            &detail("0.02", "3", 2_000),
+            &HashSet::new(),
+            1.0,
+            1_000,
+        );
+        assert!(matches!(result, Err(Rejection::PriceChanged { .. })));
+    }
+
+    #[test]
+    fn test_verify_listing_exp_and_expiry() {
+        let result = verify_listing(
+            &card(),
+            &detail("0.015", "4", 2_000),
+            &HashSet::new(),
+            1.0,
+            1_000,
+        );
+        assert!(matches!(result, Err(Rejection::ExpChanged { .. })));
+
+        let result = verify_listing(
+            &card(),
+            &detail("0.015", "3", 500),
+            &HashSet::new(),
+            1.0,
+            1_000,
+        );
+        assert_eq!(result, Err(Rejection::Expired { end_time: 500 }));
+    }
+
+    #[test]
+    fn test_verify_listing_seller() {
+        let own = HashSet::from(["8842".to_string()]);
+        let result = verify_listing(&card(), &detail("0.015", "3", 0), &own, 1.0, 1_000);
+        assert_eq!(result, Err(Rejection::OwnListing));
+
+        let mut detail = detail("0.015", "3", 0);
+        detail.base_info.owner.clear();
+        let result = verify_listing(&card(), &detail, &HashSet::new(), 1.0, 1_000);
+        assert_eq!(result, Err(Rejection::ListingChanged));
+    }
+
+    #[test]
+    fn test_verify_listing_missing_detail() {
+        let mut missing_level = detail("0.015", "3", 0);
+        missing_level.base_info.priority_level_trait = None;
+        let result = verify_listing(&card(), &missing_level, &HashSet::new(), 1.0, 1_000);
+        assert_eq!(result, Err(Rejection::MissingDetail { field: "level" }));
+
+        let mut missing_exp = detail("0.015", "3", 0);
+        missing_exp.meta_data.clear();
+        let result = verify_listing(&card(), &missing_exp, &HashSet::new(), 1.0, 1_000);
+        assert_eq!(result, Err(Rejection::MissingDetail { field: "exp" }));
+
+        let result = verify_listing(
+            &card(),
+            &detail("0.015", "", 0),
+            &HashSet::new(),
+            1.0,
+            1_000,
+        );
+        assert_eq!(result, Err(Rejection::MissingDetail { field: "exp" }));
+    }
+
+    #[test]
+    fn test_verify_listing_balance() {
+        let result = verify_listing(
+            &card(),
+            &detail("0.015", "3", 0),
+            &HashSet::new(),
+            0.01,
+            1_000,
+        );
+        assert!(matches!(result, Err(Rejection::InsufficientBalance { .. })));
+    }
+}
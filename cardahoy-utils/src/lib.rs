@@ -1,6 +1,10 @@
+use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use std::{fs::File, io::Read};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
 pub fn timestamp() -> i64 {
     let now: DateTime<Utc> = Utc::now();
@@ -8,7 +12,32 @@ pub fn timestamp() -> i64 {
     timestamp_millis
 }
 
-#[derive(Deserialize, Debug)]
+/// Where request timestamps come from, so tests can pin `reqTimestamp`.
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the Unix epoch.
+    fn timestamp(&self) -> i64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn timestamp(&self) -> i64 {
+        timestamp()
+    }
+}
+
+/// A clock stopped at the given millisecond timestamp.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub i64);
+
+impl Clock for FixedClock {
+    fn timestamp(&self) -> i64 {
+        self.0
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub client_app_id: String,
     pub authorization: String,
@@ -17,15 +46,476 @@ pub struct Config {
     pub faction: Vec<String>,
     pub rarity: Vec<String>,
     pub foil: Vec<String>,
+    // 交易记录文件
+    #[serde(default = "default_ledger_path")]
+    pub ledger_path: String,
+    // 待挂单、挂单与下单记录，退出时保存，启动时恢复
+    #[serde(default = "default_state_path")]
+    pub state_path: String,
+    // 行情快照文件，设置后记录扫描结果用于回测
+    #[serde(default)]
+    pub history_path: Option<String>,
+    // Prometheus 指标监听地址，例如 127.0.0.1:9184，设置后提供 /metrics
+    #[serde(default)]
+    pub metrics_addr: Option<String>,
+    // 守护进程控制接口监听地址；监听非本机地址时必须设置 control_token
+    #[serde(default)]
+    pub control_addr: Option<String>,
+    // 控制接口的访问令牌，设置后请求需带 Authorization: Bearer <token>，
+    // 可用 CARDAHOY_CONTROL_TOKEN 设置
+    #[serde(default)]
+    pub control_token: Option<String>,
+    // RSA 公钥文件，不设置时使用内置公钥
+    #[serde(default)]
+    pub rsa_key_path: Option<String>,
+    // RSA 公钥 PEM 内容，优先于 rsa_key_path，可用 CARDAHOY_RSA_PUBLIC_KEY 设置
+    #[serde(default)]
+    pub rsa_public_key: Option<String>,
+    #[serde(default)]
+    pub sell: SellConfig,
+    #[serde(default)]
+    pub gold: GoldConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    // 价格提醒，每次扫描时检查，只通知不购买
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+    // 每天（本地时间）最多买入的金额，不设置则只受余额限制
+    #[serde(default)]
+    pub daily_budget: Option<f64>,
+    // 同一账户两次请求之间的最小间隔（毫秒）
+    #[serde(default)]
+    pub request_interval_ms: u64,
+    // 行情缓存时间（秒），缓存期内的扫描复用同一份结果，不设置时多账户为 5 秒，单账户不缓存
+    #[serde(default)]
+    pub market_cache_secs: Option<u64>,
+    // 多账户，见 [`Profile`]
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+fn default_ledger_path() -> String {
+    "ledger.csv".into()
+}
+
+fn default_state_path() -> String {
+    "state.json".into()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SellConfig {
+    // 是否自动挂单卖出买到的卡牌
+    pub enabled: bool,
+    // 目标利润率，基于成本价（已扣除手续费）
+    pub markup: f64,
+    // 最低利润率，重新挂单降价时不会低于此值
+    pub min_markup: f64,
+    // 挂单过期未成交时，每次降价的比例
+    pub reprice_step: f64,
+    // 最多重新挂单次数，超过后不再管理该卡牌
+    pub max_relists: u32,
+}
+
+impl Default for SellConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            markup: 0.2,
+            min_markup: 0.05,
+            reprice_step: 0.05,
+            max_relists: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GoldConfig {
+    // 金卡 Price/EXP 低于普卡均价的倍数时买入
+    pub ratio: f64,
+    // 按稀有度覆盖 ratio，键为 Common, Rare, Epic, Legendary
+    pub rarity_ratio: HashMap<String, f64>,
+}
+
+impl Default for GoldConfig {
+    fn default() -> Self {
+        Self {
+            ratio: 1.1,
+            rarity_ratio: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DaemonConfig {
+    // 每轮扫描间隔（秒）
+    pub interval_secs: u64,
+    // 每轮额外随机等待 0 到 jitter_secs 秒
+    pub jitter_secs: u64,
+    // 静默时段，本地时间 "HH:MM-HH:MM"，可跨零点
+    pub quiet_hours: Vec<String>,
+    // 任务运行间隔（秒），任务名为策略名或 sell，0 表示每轮运行，未列出的任务不运行
+    pub schedules: HashMap<String, u64>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            jitter_secs: 10,
+            quiet_hours: vec![],
+            schedules: HashMap::from([("cheap_cards".into(), 0), ("sell".into(), 0)]),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub sinks: Vec<NotifySink>,
+    // 同一通知渠道同一种事件的最小间隔（秒）
+    pub rate_limit_secs: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            sinks: vec![],
+            rate_limit_secs: 60,
+        }
+    }
+}
+
+/// Where notifications are delivered. `events` limits which event kinds the
+/// sink receives; empty means all.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifySink {
+    // POST JSON 到指定地址
+    Webhook {
+        url: String,
+        #[serde(default)]
+        events: Vec<String>,
+    },
+    // 通过 sh -c 执行命令，JSON 从标准输入传入
+    Command {
+        command: String,
+        #[serde(default)]
+        events: Vec<String>,
+    },
+    // 以 JSON Lines 追加到文件
+    Inbox {
+        path: String,
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+impl NotifySink {
+    pub fn events(&self) -> &[String] {
+        match self {
+            NotifySink::Webhook { events, .. }
+            | NotifySink::Command { events, .. }
+            | NotifySink::Inbox { events, .. } => events,
+        }
+    }
+}
+
+/// A price alert. Cards are selected by `card` (English or Chinese name, or
+/// id) and/or `rarity` and `gold`; an unset selector matches every card. The
+/// alert fires when any of the set conditions starts to hold.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AlertRule {
+    pub card: Option<String>,
+    // Common, Rare, Epic, Legendary
+    pub rarity: Option<String>,
+    pub gold: Option<bool>,
+    // 底价 Price/EXP 低于此值
+    pub price_per_exp_below: Option<f64>,
+    // 底价在 window_mins 分钟内跌幅达到此百分比
+    pub floor_drop_percent: Option<f64>,
+    pub window_mins: Option<u64>,
+    // 在售数量低于此值
+    pub quantity_below: Option<u32>,
+}
+
+/// A named account. Unset fields fall back to the top-level config; `sell`,
+/// `gold` and `daemon` replace the top-level table as a whole.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    pub client_app_id: Option<String>,
+    pub authorization: Option<String>,
+    pub cookies: Option<String>,
+    pub user_agent: Option<String>,
+    // 不设置时为 ledger-<名称>.csv，各账户分开记账
+    pub ledger_path: Option<String>,
+    // 不设置时为 state-<名称>.json
+    pub state_path: Option<String>,
+    pub daily_budget: Option<f64>,
+    pub request_interval_ms: Option<u64>,
+    pub sell: Option<SellConfig>,
+    pub gold: Option<GoldConfig>,
+    // 各账户运行的策略与频率
+    pub daemon: Option<DaemonConfig>,
 }
 
+// 多账户时的默认行情缓存时间（秒）
+pub const PROFILE_MARKET_CACHE_SECS: u64 = 5;
+
+// 环境变量覆盖配置项的前缀，嵌套字段用 __ 分隔，例如 CARDAHOY_SELL__ENABLED
+pub const ENV_PREFIX: &str = "CARDAHOY_";
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
 impl Config {
-    pub fn new() -> Self {
-        let mut file = File::open("config.toml").expect("unable to load config file");
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .expect("unable to read file");
-        let config = toml::from_str(&contents).expect("unable to parse config file");
-        config
+    /// Loads the config file at `path` and overlays `CARDAHOY_*` environment
+    /// variables on top of it.
+    ///
+    /// `CARDAHOY_AUTHORIZATION` overrides `authorization`, and `__` reaches
+    /// into tables, e.g. `CARDAHOY_DAEMON__INTERVAL_SECS=60`. A value takes the
+    /// type of the key it replaces; for keys missing from the file it is read
+    /// as a TOML value (number, boolean, array) and otherwise as a string.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read config file {}", path.display()))?;
+        Self::from_sources(&contents, std::env::vars())
+            .with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Parses `contents` as TOML with `CARDAHOY_*` entries of `env` applied.
+    pub fn from_sources(
+        contents: &str,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(contents)?;
+        for (key, value) in env {
+            let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let path: Vec<String> = name.split("__").map(|k| k.to_lowercase()).collect();
+            if path.iter().any(|k| k.is_empty()) {
+                bail!("invalid environment variable {}", key);
+            }
+            set_path(&mut table, &path, &value).with_context(|| format!("invalid {}", key))?;
+        }
+        Ok(toml::Value::Table(table).try_into()?)
+    }
+
+    /// The config of the named profile, with its overrides applied on top of
+    /// the shared settings.
+    pub fn profile(&self, name: &str) -> Result<Config> {
+        let Some(profile) = self.profiles.get(name) else {
+            bail!(
+                "未知账户 {:?}, 已配置: {}",
+                name,
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            );
+        };
+        let mut config = self.clone();
+        config.profiles.clear();
+        if config.market_cache_secs.is_none() {
+            config.market_cache_secs = Some(PROFILE_MARKET_CACHE_SECS);
+        }
+
+        let profile = profile.clone();
+        for (value, field) in [
+            (profile.client_app_id, &mut config.client_app_id),
+            (profile.authorization, &mut config.authorization),
+            (profile.cookies, &mut config.cookies),
+            (profile.user_agent, &mut config.user_agent),
+        ] {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        config.ledger_path = profile
+            .ledger_path
+            .unwrap_or_else(|| format!("ledger-{}.csv", name));
+        config.state_path = profile
+            .state_path
+            .unwrap_or_else(|| format!("state-{}.json", name));
+        if profile.daily_budget.is_some() {
+            config.daily_budget = profile.daily_budget;
+        }
+        if let Some(interval) = profile.request_interval_ms {
+            config.request_interval_ms = interval;
+        }
+        if let Some(sell) = profile.sell {
+            config.sell = sell;
+        }
+        if let Some(gold) = profile.gold {
+            config.gold = gold;
+        }
+        if let Some(daemon) = profile.daemon {
+            config.daemon = daemon;
+        }
+        Ok(config)
+    }
+}
+
+// Config 与 Profile 中的字符串配置项，值可能是纯数字的密钥或 Cookie
+const STRING_KEYS: &[&str] = &[
+    "client_app_id",
+    "authorization",
+    "cookies",
+    "user_agent",
+    "ledger_path",
+    "state_path",
+    "history_path",
+    "metrics_addr",
+    "control_addr",
+    "control_token",
+    "rsa_key_path",
+    "rsa_public_key",
+];
+
+fn set_path(table: &mut toml::Table, path: &[String], raw: &str) -> Result<()> {
+    let (key, rest) = path.split_first().expect("empty config path");
+    if rest.is_empty() {
+        let value = parse_env_value(raw, key, table.get(key));
+        table.insert(key.clone(), value);
+        return Ok(());
+    }
+    let entry = table
+        .entry(key.clone())
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    match entry {
+        toml::Value::Table(child) => set_path(child, rest, raw),
+        _ => bail!("{} is not a table", key),
+    }
+}
+
+// 按目标配置项的类型解析环境变量：字符串配置项（包括文件中没有的）保持原样，
+// 其余按 TOML 值解析
+fn parse_env_value(raw: &str, key: &str, existing: Option<&toml::Value>) -> toml::Value {
+    if STRING_KEYS.contains(&key) || matches!(existing, Some(toml::Value::String(_))) {
+        return toml::Value::String(raw.to_string());
+    }
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        client_app_id = "app"
+        authorization = "from-file"
+        cookies = ""
+        user_agent = "agent"
+        faction = []
+        rarity = []
+        foil = []
+
+        [sell]
+        enabled = false
+    "#;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let config = Config::from_sources(
+            CONFIG,
+            env(&[
+                ("CARDAHOY_AUTHORIZATION", "12345"),
+                ("CARDAHOY_COOKIES", "a=1; b=2"),
+                ("CARDAHOY_SELL__ENABLED", "true"),
+                ("CARDAHOY_DAEMON__INTERVAL_SECS", "5"),
+                ("CARDAHOY_FACTION", r#"["Neutral"]"#),
+                ("OTHER_AUTHORIZATION", "ignored"),
+            ]),
+        )
+        .unwrap();
+        // 文件中为字符串的配置项保持字符串
+        assert_eq!(config.authorization, "12345");
+        assert_eq!(config.cookies, "a=1; b=2");
+        assert!(config.sell.enabled);
+        assert_eq!(config.daemon.interval_secs, 5);
+        assert_eq!(config.faction, vec!["Neutral"]);
+        assert_eq!(config.ledger_path, "ledger.csv");
+    }
+
+    #[test]
+    fn test_env_numeric_strings() {
+        // 文件中没有的字符串配置项，纯数字的值也不能解析为整数
+        let contents = CONFIG.replace("authorization = \"from-file\"", "");
+        let config = Config::from_sources(
+            &format!("{}\n[profiles.x]", contents),
+            env(&[
+                ("CARDAHOY_AUTHORIZATION", "12345"),
+                ("CARDAHOY_PROFILES__X__COOKIES", "1"),
+                ("CARDAHOY_PROFILES__X__DAILY_BUDGET", "3"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.authorization, "12345");
+        let profile = config.profile("x").unwrap();
+        assert_eq!(profile.cookies, "1");
+        assert_eq!(profile.daily_budget, Some(3.0));
+    }
+
+    #[test]
+    fn test_profiles() {
+        // 顶层配置项需要写在 [sell] 表之前
+        let contents = format!(
+            "daily_budget = 10.0\n{}\n{}",
+            CONFIG,
+            r#"
+            [profiles.main]
+            [profiles.alt]
+            user_agent = "alt-agent"
+            ledger_path = "alt.csv"
+            daily_budget = 2.5
+            [profiles.alt.sell]
+            enabled = true
+            "#
+        );
+        let config = Config::from_sources(
+            &contents,
+            env(&[("CARDAHOY_PROFILES__ALT__AUTHORIZATION", "alt-token")]),
+        )
+        .unwrap();
+
+        let main = config.profile("main").unwrap();
+        assert_eq!(main.authorization, "from-file");
+        assert_eq!(main.ledger_path, "ledger-main.csv");
+        assert_eq!(main.state_path, "state-main.json");
+        assert_eq!(main.daily_budget, Some(10.0));
+        assert_eq!(main.market_cache_secs, Some(PROFILE_MARKET_CACHE_SECS));
+        assert!(!main.sell.enabled);
+
+        let alt = config.profile("alt").unwrap();
+        assert_eq!(alt.authorization, "alt-token");
+        assert_eq!(alt.user_agent, "alt-agent");
+        assert_eq!(alt.client_app_id, "app");
+        assert_eq!(alt.ledger_path, "alt.csv");
+        assert_eq!(alt.daily_budget, Some(2.5));
+        assert!(alt.sell.enabled);
+        assert!(alt.profiles.is_empty());
+        assert!(config.profile("missing").is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let err = Config::from_sources(CONFIG, env(&[("CARDAHOY_SELL__ENABLED", "yes")]));
+        assert!(err.is_err());
+        let err = Config::from_sources(CONFIG, env(&[("CARDAHOY_COOKIES__X", "1")]));
+        assert!(err.is_err());
+        assert!(Config::from_sources("authorization = ", env(&[])).is_err());
+        assert!(Config::load("/nonexistent/config.toml").is_err());
     }
 }